@@ -1,7 +1,15 @@
 use async_mutex::Mutex;
+use async_trait::async_trait;
+use futures::future::try_join_all;
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::Instant;
 
+use bitwarden::secrets_manager::projects::ProjectGetRequest;
 use bitwarden::secrets_manager::secrets::{
-    SecretIdentifiersByProjectRequest, SecretIdentifiersResponse, SecretsGetRequest,
+    SecretCreateRequest, SecretGetRequest, SecretIdentifiersByProjectRequest,
+    SecretIdentifiersResponse, SecretPutRequest, SecretResponse, SecretsDeleteRequest,
+    SecretsGetRequest,
 };
 use bitwarden::{
     auth::login::AccessTokenLoginRequest,
@@ -9,116 +17,445 @@ use bitwarden::{
     Client,
 };
 use tokio_retry::strategy::{jitter, ExponentialBackoff};
-use tokio_retry::Retry;
+use tokio_retry::RetryIf;
 use uuid::Uuid;
 
-use crate::config_yaml::Secrets;
-use tracing::{error, info};
+use crate::config_yaml::{Fetch, FetchedSecrets, SecretMetadata, Secrets, ServerUrls};
+use crate::error::{BitwardenError, ProviderError};
+use crate::provider::SecretsProvider;
+use tracing::{debug, error, info, info_span, Instrument};
+
+fn classify(err: bitwarden::error::Error, project_id: Option<&str>) -> BitwardenError {
+    use bitwarden::error::Error;
+
+    match err {
+        Error::AccessTokenInvalid(_) => BitwardenError::InvalidToken,
+        Error::IdentityFail(_) | Error::NotAuthenticated => BitwardenError::ExpiredToken,
+        Error::ResponseContent { status, message } => {
+            BitwardenError::from_status(status.as_u16(), message, project_id)
+        }
+        Error::Reqwest(err) => match err.status() {
+            Some(status) => {
+                BitwardenError::from_status(status.as_u16(), err.to_string(), project_id)
+            }
+            None => BitwardenError::Network(err.to_string()),
+        },
+        err => BitwardenError::Other(err.to_string()),
+    }
+}
 
 pub struct BitwardenClient {
-    _identity_url: String,
-    _api_url: String,
-    _user_agent: String,
-    _device_type: DeviceType,
-    _access_token: String,
+    server_urls: ServerUrls,
+    access_token: String,
+    state_file: Option<PathBuf>,
+    fetch: Fetch,
     client: Mutex<Client>,
 }
 
 impl BitwardenClient {
-    pub async fn new(access_token: String) -> Self {
-        let identity_url = String::from("https://identity.bitwarden.com");
-        let api_url = String::from("https://api.bitwarden.com");
-        let user_agent = String::from("Bitwarden Rust-SDK");
-        let device_type = DeviceType::SDK;
-
-        let mut client = Client::new(Some(ClientSettings {
-            identity_url: identity_url.to_owned(),
-            api_url: api_url.to_owned(),
-            user_agent: user_agent.to_owned(),
-            device_type,
-        }));
+    /// Logs in using the access token. Given a state file, the session of a previous login
+    /// is reused until it expires instead of authenticating against the identity service again,
+    /// the SDK keeps the session encrypted with the key of the access token
+    pub async fn new(
+        access_token: String,
+        server_urls: &ServerUrls,
+        state_file: Option<PathBuf>,
+        fetch: Fetch,
+    ) -> Result<Self, BitwardenError> {
+        let bitwarden_client = Self {
+            client: Mutex::new(Self::client(server_urls)),
+            server_urls: server_urls.clone(),
+            access_token,
+            state_file,
+            fetch,
+        };
+
+        Self::login(
+            &mut *bitwarden_client.client.lock().await,
+            &bitwarden_client.access_token,
+            &bitwarden_client.state_file,
+        )
+        .await?;
+        Ok(bitwarden_client)
+    }
+
+    fn client(server_urls: &ServerUrls) -> Client {
+        Client::new(Some(ClientSettings {
+            identity_url: server_urls.identity_url.clone(),
+            api_url: server_urls.api_url.clone(),
+            user_agent: String::from("Bitwarden Rust-SDK"),
+            device_type: DeviceType::SDK,
+        }))
+    }
+
+    /// Creates another logged in client, the SDK client can only send one request at a time
+    async fn connect(&self) -> Result<Client, BitwardenError> {
+        let mut client = Self::client(&self.server_urls);
+        Self::login(&mut client, &self.access_token, &self.state_file).await?;
+        Ok(client)
+    }
 
+    async fn login(
+        client: &mut Client,
+        access_token: &str,
+        state_file: &Option<PathBuf>,
+    ) -> Result<(), BitwardenError> {
         client
             .access_token_login(&AccessTokenLoginRequest {
                 access_token: access_token.to_owned(),
+                state_file: state_file.clone(),
             })
             .await
-            .unwrap_or_else(|_| {
+            .map_err(|err| {
                 error!(message = "Failed to login using access token");
-                std::process::exit(1);
-            });
-
-        Self {
-            client: Mutex::new(client),
-            _access_token: access_token,
-            _identity_url: identity_url,
-            _api_url: api_url,
-            _user_agent: user_agent,
-            _device_type: device_type,
+                classify(err, None)
+            })?;
+        Ok(())
+    }
+
+    /// Runs a request, retrying it once after a fresh login if the server rejected a persisted
+    /// session, e.g. because it was revoked before it expired
+    async fn with_session<T, F, Fut>(&self, request: F) -> Result<T, ProviderError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, ProviderError>>,
+    {
+        match request().await {
+            Err(ProviderError::Bitwarden(BitwardenError::ExpiredToken)) => match &self.state_file {
+                Some(state_file) => {
+                    info!(message = "Persisted session was rejected, logging in again");
+                    let _ = std::fs::remove_file(state_file);
+                    Self::login(
+                        &mut *self.client.lock().await,
+                        &self.access_token,
+                        &self.state_file,
+                    )
+                    .await?;
+                    request().await
+                }
+                None => Err(ProviderError::Bitwarden(BitwardenError::ExpiredToken)),
+            },
+            result => result,
         }
     }
 
     pub async fn get_secrets_by_project_id<'a, T: AsRef<str>>(
-        &mut self,
+        &self,
         project_id: T,
-    ) -> Result<Secrets<'a>, Box<dyn std::error::Error>> {
+    ) -> Result<FetchedSecrets<'a>, BitwardenError> {
+        let project_id = project_id.as_ref();
+        let project_uuid = Self::parse_project_id(project_id)?;
+        let started = Instant::now();
+
         let secret_identifiers = async {
             let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(4);
             let request = || async {
                 let secrets_by_project_request = SecretIdentifiersByProjectRequest {
-                    project_id: Uuid::parse_str(project_id.as_ref())?,
+                    project_id: project_uuid,
                 };
 
                 info!(message = "Fetching secret IDs");
 
-                Ok(self
-                    .client
+                self.client
                     .lock()
                     .await
                     .secrets()
                     .list_by_project(&secrets_by_project_request)
-                    .await?)
+                    .await
+                    .map_err(|err| classify(err, Some(project_id)))
             };
 
-            let result: Result<SecretIdentifiersResponse, Box<dyn std::error::Error>> =
-                Retry::spawn(retry_strategy, request).await;
+            let result: Result<SecretIdentifiersResponse, BitwardenError> =
+                RetryIf::spawn(retry_strategy, request, BitwardenError::is_transient).await;
 
-            result.unwrap()
-        };
+            debug!(message = format!("Fetched secret IDs in {:?}", started.elapsed()));
+            result
+        }
+        .instrument(info_span!("list_secrets", project_id));
 
         let ids: Vec<Uuid> = secret_identifiers
-            .await
+            .await?
             .data
             .into_iter()
             .map(|ident| ident.id)
             .collect();
 
-        let secrets = async {
-            let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(4);
-            let request = || async {
-                let secrets_get_request = SecretsGetRequest { ids: ids.clone() };
+        let batches: Vec<&[Uuid]> = ids.chunks((*self.fetch.batch_size).max(1)).collect();
+        // Without a persisted session every additional client would log in again, batches are
+        // then fetched sequentially by the client of the provider
+        let workers = match self.state_file {
+            Some(_) => (*self.fetch.concurrency).clamp(1, batches.len().max(1)),
+            None => 1,
+        };
 
-                info!(message = "Fetching secrets");
+        // Each worker fetches every n-th batch using its own client, the first one reuses the
+        // client of the provider while the others log in using the persisted session
+        let clients: Vec<Mutex<Client>> = try_join_all((1..workers).map(|_| self.connect()))
+            .await?
+            .into_iter()
+            .map(Mutex::new)
+            .collect();
 
-                Ok(self
-                    .client
-                    .lock()
-                    .await
-                    .secrets()
-                    .get_by_ids(secrets_get_request)
-                    .await?
-                    .data
-                    .into_iter()
-                    .map(|secret| (secret.key, secret.value))
-                    .collect())
+        let worker_batches = (0..workers).map(|worker| {
+            let client = match worker {
+                0 => &self.client,
+                worker => &clients[worker - 1],
             };
+            let batches: Vec<(usize, &[Uuid])> = batches
+                .iter()
+                .copied()
+                .enumerate()
+                .skip(worker)
+                .step_by(workers)
+                .collect();
 
-            let result: Result<Secrets<'a>, Box<dyn std::error::Error>> =
-                Retry::spawn(retry_strategy, request).await;
+            async move {
+                let mut secrets = Vec::new();
+                for (batch, ids) in batches {
+                    secrets.extend(
+                        self.get_secrets_batch(client, project_id, ids)
+                            .instrument(info_span!("get_secrets", batch, size = ids.len()))
+                            .await?,
+                    );
+                }
+                Ok::<_, BitwardenError>(secrets)
+            }
+        });
 
-            result
+        info!(
+            message = format!(
+                "Fetching {} secrets in {} batches",
+                ids.len(),
+                batches.len()
+            )
+        );
+
+        let secrets: Vec<SecretResponse> = try_join_all(worker_batches)
+            .instrument(info_span!("fetch_secrets", project_id, workers))
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        debug!(message = format!("Fetched secrets in {:?}", started.elapsed()));
+        Ok(FetchedSecrets {
+            metadata: secrets
+                .iter()
+                .map(|secret| {
+                    let metadata = SecretMetadata {
+                        id: secret.id.to_string(),
+                        note: Some(secret.note.trim().to_string()).filter(|note| !note.is_empty()),
+                        created: Some(secret.creation_date.to_rfc3339()),
+                        revised: Some(secret.revision_date.to_rfc3339()),
+                    };
+                    (secret.key.clone(), metadata)
+                })
+                .collect(),
+            secrets: secrets
+                .into_iter()
+                .map(|secret| (secret.key, secret.value))
+                .collect(),
+        })
+    }
+
+    async fn get_secrets_batch(
+        &self,
+        client: &Mutex<Client>,
+        project_id: &str,
+        ids: &[Uuid],
+    ) -> Result<Vec<SecretResponse>, BitwardenError> {
+        let started = Instant::now();
+        let retry_strategy = ExponentialBackoff::from_millis(10).map(jitter).take(4);
+        let request = || async {
+            let secrets_get_request = SecretsGetRequest { ids: ids.to_vec() };
+
+            Ok(client
+                .lock()
+                .await
+                .secrets()
+                .get_by_ids(secrets_get_request)
+                .await
+                .map_err(|err| classify(err, Some(project_id)))?
+                .data)
         };
 
-        secrets.await
+        let result: Result<Vec<_>, BitwardenError> =
+            RetryIf::spawn(retry_strategy, request, BitwardenError::is_transient).await;
+
+        debug!(message = format!("Fetched {} secrets in {:?}", ids.len(), started.elapsed()));
+        result
+    }
+
+    fn parse_project_id(project_id: &str) -> Result<Uuid, BitwardenError> {
+        Uuid::parse_str(project_id)
+            .map_err(|_| BitwardenError::InvalidProjectId(project_id.to_string()))
+    }
+
+    /// Looks up a secret of the project by its key, Secrets Manager itself allows duplicate keys
+    /// in which case the first one is used
+    async fn find_secret(
+        &self,
+        project_id: &str,
+        key: &str,
+    ) -> Result<Option<SecretResponse>, BitwardenError> {
+        let project_uuid = Self::parse_project_id(project_id)?;
+        let mut client = self.client.lock().await;
+
+        let identifier = client
+            .secrets()
+            .list_by_project(&SecretIdentifiersByProjectRequest {
+                project_id: project_uuid,
+            })
+            .await
+            .map_err(|err| classify(err, Some(project_id)))?
+            .data
+            .into_iter()
+            .find(|identifier| identifier.key == key);
+
+        match identifier {
+            Some(identifier) => Ok(Some(
+                client
+                    .secrets()
+                    .get(&SecretGetRequest { id: identifier.id })
+                    .await
+                    .map_err(|err| classify(err, Some(project_id)))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn update_secret(
+        &self,
+        project_id: &str,
+        secret: SecretResponse,
+        key: &str,
+        value: &str,
+    ) -> Result<(), BitwardenError> {
+        let project_uuid = Self::parse_project_id(project_id)?;
+
+        info!(message = format!("Updating secret {:?}", secret.key));
+
+        self.client
+            .lock()
+            .await
+            .secrets()
+            .update(&SecretPutRequest {
+                id: secret.id,
+                organization_id: secret.organization_id,
+                key: key.to_string(),
+                value: value.to_string(),
+                note: secret.note,
+                // Keep the projects of the secret, it was found in the project of the profile
+                project_ids: Some(vec![secret.project_id.unwrap_or(project_uuid)]),
+            })
+            .await
+            .map_err(|err| classify(err, Some(project_id)))?;
+        Ok(())
+    }
+}
+
+impl BitwardenClient {
+    // Unlike fetching, writes are not retried as creating a secret is not idempotent
+    async fn set_secret(
+        &self,
+        reference: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<(), ProviderError> {
+        if let Some(secret) = self.find_secret(reference, key).await? {
+            return Ok(self.update_secret(reference, secret, key, value).await?);
+        }
+
+        let project_uuid = Self::parse_project_id(reference)?;
+        let mut client = self.client.lock().await;
+        let organization_id = client
+            .projects()
+            .get(&ProjectGetRequest { id: project_uuid })
+            .await
+            .map_err(|err| classify(err, Some(reference)))?
+            .organization_id;
+
+        info!(message = format!("Creating secret {:?}", key));
+
+        client
+            .secrets()
+            .create(&SecretCreateRequest {
+                organization_id,
+                key: key.to_string(),
+                value: value.to_string(),
+                note: String::new(),
+                project_ids: Some(vec![project_uuid]),
+            })
+            .await
+            .map_err(|err| classify(err, Some(reference)))?;
+        Ok(())
+    }
+
+    async fn delete_secret(&self, reference: &str, key: &str) -> Result<(), ProviderError> {
+        let secret = self
+            .find_secret(reference, key)
+            .await?
+            .ok_or_else(|| ProviderError::SecretNotFound(key.to_string()))?;
+
+        info!(message = format!("Deleting secret {:?}", key));
+
+        self.client
+            .lock()
+            .await
+            .secrets()
+            .delete(SecretsDeleteRequest {
+                ids: vec![secret.id],
+            })
+            .await
+            .map_err(|err| classify(err, Some(reference)))?;
+        Ok(())
+    }
+
+    async fn rename_secret(
+        &self,
+        reference: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<(), ProviderError> {
+        if self.find_secret(reference, to).await?.is_some() {
+            return Err(ProviderError::SecretExists(to.to_string()));
+        }
+        let secret = self
+            .find_secret(reference, from)
+            .await?
+            .ok_or_else(|| ProviderError::SecretNotFound(from.to_string()))?;
+
+        let value = secret.value.clone();
+        Ok(self.update_secret(reference, secret, to, &value).await?)
+    }
+}
+
+#[async_trait(?Send)]
+impl SecretsProvider for BitwardenClient {
+    async fn fetch<'a>(&self, reference: &str) -> Result<Secrets<'a>, ProviderError> {
+        Ok(self.fetch_with_metadata(reference).await?.secrets)
+    }
+
+    async fn fetch_with_metadata<'a>(
+        &self,
+        reference: &str,
+    ) -> Result<FetchedSecrets<'a>, ProviderError> {
+        self.with_session(|| async { Ok(self.get_secrets_by_project_id(reference).await?) })
+            .await
+    }
+
+    async fn set(&self, reference: &str, key: &str, value: &str) -> Result<(), ProviderError> {
+        self.with_session(|| self.set_secret(reference, key, value))
+            .await
+    }
+
+    async fn delete(&self, reference: &str, key: &str) -> Result<(), ProviderError> {
+        self.with_session(|| self.delete_secret(reference, key))
+            .await
+    }
+
+    async fn rename(&self, reference: &str, from: &str, to: &str) -> Result<(), ProviderError> {
+        self.with_session(|| self.rename_secret(reference, from, to))
+            .await
     }
 }
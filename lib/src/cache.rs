@@ -1,10 +1,30 @@
-use crate::time::is_date_older_than_n_seconds;
+use crate::time::{format_duration, is_date_older_than_n_seconds, seconds_since};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use fs2::FileExt;
 use semver::Version;
 use serde::{Deserialize, Serialize};
-use std::{fs, future::Future, path::PathBuf, time::SystemTime};
-use tracing::info;
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fmt::{self, Debug, Display},
+    fs,
+    future::Future,
+    io::Write,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+use tabular::{Row, Table};
+use tempfile::NamedTempFile;
+use tracing::{info, warn};
 
-use crate::config_yaml::Secrets;
+use crate::config_yaml::{FetchedSecrets, Secrets, SecretsMetadata};
+use crate::crypto::{self, Key};
+use crate::fs::create_private_dir_all;
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
 
 mod version_serde {
     use semver::Version;
@@ -26,112 +46,827 @@ mod version_serde {
     }
 }
 
+/// Version of the cache entry format, independent of the version of bwenv.
+/// Bump it and add a step to `migrate` whenever older entries need to be transformed.
+pub const SCHEMA_VERSION: u32 = 2;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CacheEntry<'a> {
+    #[serde(default)]
+    pub schema: u32,
     last_revalidation: u64,
     pub variables: Secrets<'a>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: SecretsMetadata,
     #[serde(with = "version_serde")]
     pub version: Version,
+    #[serde(flatten)]
+    pub identity: Identity,
+    #[serde(default)]
+    pub seeded: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mac: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Identity {
+    #[serde(default)]
+    pub project_ids: Vec<String>,
+    #[serde(default)]
+    pub token_fingerprint: String,
+    #[serde(skip)]
+    integrity_key: Option<Key>,
+}
+
+impl PartialEq for Identity {
+    fn eq(&self, other: &Self) -> bool {
+        self.project_ids == other.project_ids && self.token_fingerprint == other.token_fingerprint
+    }
+}
+
+impl Identity {
+    pub fn new(project_ids: Vec<String>, token: &str) -> Self {
+        Self {
+            project_ids,
+            token_fingerprint: crypto::fingerprint(token.as_bytes()),
+            integrity_key: Some(crypto::derive_key(
+                token.as_bytes(),
+                "bwenv cache integrity",
+            )),
+        }
+    }
+
+    fn project_key(&self) -> String {
+        match self.project_ids.is_empty() {
+            true => String::from("default"),
+            false => self.project_ids.join("+"),
+        }
+    }
+}
+
+impl<'a> CacheEntry<'a> {
+    pub fn age(&self) -> u64 {
+        seconds_since(self.last_revalidation)
+    }
+
+    fn signed_payload(&self) -> Vec<u8> {
+        let variables: BTreeMap<&str, &str> = self
+            .variables
+            .iter()
+            .map(|(key, value)| (key.as_ref(), value.as_ref()))
+            .collect();
+        let payload = (
+            self.last_revalidation,
+            self.version.to_string(),
+            &self.identity.project_ids,
+            &self.identity.token_fingerprint,
+            self.seeded,
+            variables,
+        );
+        // Entries without metadata keep the payload of schema version 1, so they still verify
+        match self.metadata.is_empty() {
+            true => serde_json::to_vec(&payload),
+            false => serde_json::to_vec(&(payload, &self.metadata)),
+        }
+        .unwrap()
+    }
+
+    fn sign(&mut self, key: &Key) {
+        self.mac = Some(STANDARD.encode(crypto::sign(key, &self.signed_payload())));
+    }
+
+    fn verify(&self, key: &Key) -> bool {
+        self.mac
+            .as_ref()
+            .and_then(|mac| STANDARD.decode(mac).ok())
+            .is_some_and(|mac| crypto::verify(key, &self.signed_payload(), &mac))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedCacheEntry {
+    encrypted: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheStatus {
+    pub profile: String,
+    pub project: String,
+    pub path: PathBuf,
+    pub readable: bool,
+    /// Whether the signature of the entry is valid, `None` if it could not be checked
+    /// as the token of the profile is unknown
+    pub integrity: Option<bool>,
+    pub age: Option<u64>,
+    pub revalidates_in: Option<u64>,
+    pub version: Option<String>,
+    pub keys: Option<usize>,
+    pub seeded: bool,
+}
+
+impl CacheStatus {
+    fn new(
+        profile: String,
+        path: PathBuf,
+        cache_entry: Option<CacheEntry>,
+        integrity: Option<bool>,
+        max_age: &u64,
+    ) -> Self {
+        let project = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let age = cache_entry.as_ref().map(CacheEntry::age);
+        Self {
+            profile,
+            project,
+            path,
+            readable: cache_entry.is_some(),
+            integrity,
+            age,
+            revalidates_in: age.map(|age| max_age.saturating_sub(age)),
+            version: cache_entry
+                .as_ref()
+                .map(|cache_entry| cache_entry.version.to_string()),
+            keys: cache_entry
+                .as_ref()
+                .map(|cache_entry| cache_entry.variables.len()),
+            seeded: cache_entry.is_some_and(|cache_entry| cache_entry.seeded),
+        }
+    }
+}
+
+pub fn status_table(statuses: &[CacheStatus]) -> String {
+    fn or_unknown<T: ToString>(value: Option<T>) -> String {
+        value
+            .map(|value| value.to_string())
+            .unwrap_or_else(|| String::from("-"))
+    }
+
+    let mut table = Table::new("{:<}  {:<}  {:>}  {:>}  {:<}  {:>}  {:<}  {:<}  {:<}");
+    table.add_row(
+        Row::new()
+            .with_cell("PROFILE")
+            .with_cell("PROJECT")
+            .with_cell("AGE")
+            .with_cell("REVALIDATES IN")
+            .with_cell("VERSION")
+            .with_cell("KEYS")
+            .with_cell("READABLE")
+            .with_cell("INTEGRITY")
+            .with_cell("SEEDED"),
+    );
+    for status in statuses {
+        table.add_row(
+            Row::new()
+                .with_cell(&status.profile)
+                .with_cell(&status.project)
+                .with_cell(or_unknown(status.age.map(format_duration)))
+                .with_cell(or_unknown(status.revalidates_in.map(format_duration)))
+                .with_cell(or_unknown(status.version.as_ref()))
+                .with_cell(or_unknown(status.keys))
+                .with_cell(if status.readable { "yes" } else { "no" })
+                .with_cell(match status.integrity {
+                    Some(true) => "ok",
+                    Some(false) => "failed",
+                    None => "-",
+                })
+                .with_cell(if status.seeded { "yes" } else { "no" }),
+        );
+    }
+    table.to_string()
+}
+
+#[derive(Debug, Clone)]
+pub enum Storage {
+    Plain,
+    Encrypted(Key),
+    None,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Fallback {
+    Strict,
+    Stale { max_stale: Option<u64> },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Revalidation {
+    Blocking,
+    Background { timeout: Duration },
+}
+
+/// Refresh of a stale cache entry that was returned before revalidating it,
+/// must be awaited for the refreshed values to be written to the cache.
+pub struct BackgroundRevalidation<'c>(Pin<Box<dyn Future<Output = ()> + 'c>>);
+
+impl Future for BackgroundRevalidation<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.0.as_mut().poll(cx)
+    }
+}
+
+impl Debug for BackgroundRevalidation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BackgroundRevalidation")
+            .finish_non_exhaustive()
+    }
+}
+
+impl Storage {
+    pub fn encrypted(secret: &[u8]) -> Self {
+        Storage::Encrypted(crypto::derive_key(secret, "bwenv cache encryption"))
+    }
 }
 
 pub struct Cache<'a> {
     pub directory: PathBuf,
     version: &'a Version,
+    storage: Storage,
+    identity: Identity,
+}
+
+pub fn user_cache_directory() -> Option<PathBuf> {
+    dirs::cache_dir()
+}
+
+/// Directory of the persisted Bitwarden sessions, shared by all projects of the user. It is
+/// kept outside of the cache directory so clearing or pruning caches does not log out
+pub fn auth_state_directory() -> Option<PathBuf> {
+    user_cache_directory().map(|directory| directory.join("bwenv-auth"))
 }
 
 impl<'a> Cache<'a> {
-    pub fn new(directory: PathBuf, version: &'a Version) -> Self {
+    pub fn new(
+        directory: PathBuf,
+        version: &'a Version,
+        storage: Storage,
+        identity: Identity,
+    ) -> Self {
         Cache::<'a> {
             directory: directory.join("bwenv"),
             version,
+            storage,
+            identity,
         }
     }
 
-    pub fn get(&self, profile: &str) -> Option<CacheEntry> {
+    pub fn get<'b>(&self, profile: &str) -> Option<CacheEntry<'b>> {
+        if let Storage::None = self.storage {
+            return None;
+        }
         let cache_file_path = self.get_cache_file_path(profile);
-        let cache_entry = std::fs::read_to_string(cache_file_path).ok()?;
-        let cache_entry: CacheEntry = serde_yaml::from_str(&cache_entry).ok()?;
+        let cache_entry = match std::fs::read_to_string(cache_file_path) {
+            Ok(cache_entry) => cache_entry,
+            Err(_) => {
+                self.remove_legacy(profile);
+                return None;
+            }
+        };
+        let (mut cache_entry, migrated_from) = self
+            .decode(&cache_entry)
+            .map_err(|reason| {
+                warn!(
+                    message = format!(
+                        "Could not read cache for profile {:?}: {}, ignoring it",
+                        profile, reason
+                    )
+                );
+            })
+            .ok()?;
+        if let Some(schema) = migrated_from {
+            info!(
+                message = format!(
+                    "Migrating cache for profile {:?} from schema version {} to {}",
+                    profile, schema, SCHEMA_VERSION
+                )
+            );
+            self.write_or_warn(profile, &mut cache_entry);
+        }
         Some(cache_entry)
     }
 
-    pub async fn get_or_revalidate<'b, RevalidateFn, ReturnValue>(
-        &self,
-        profile: &str,
+    /// Removes an entry written before caches were stored per project, `<profile>.yaml` in the
+    /// cache directory. Legacy entries are unsigned and not bound to a project or token, so they
+    /// are revalidated rather than trusted.
+    fn remove_legacy(&self, profile: &str) {
+        let legacy_cache_file_path = self.get_legacy_cache_file_path(profile);
+        if fs::remove_file(&legacy_cache_file_path).is_ok() {
+            info!(
+                message = format!(
+                    "Removed cache for profile {:?} written by an older version at {:?}",
+                    profile, legacy_cache_file_path
+                )
+            );
+        }
+    }
+
+    pub fn status(&self, max_age: &u64) -> Vec<CacheStatus> {
+        let mut statuses: Vec<CacheStatus> = self
+            .profiles()
+            .into_iter()
+            .flat_map(|profile| self.profile_status(&profile, max_age))
+            .collect();
+        statuses.sort_by(|a, b| (&a.profile, &a.project).cmp(&(&b.profile, &b.project)));
+        statuses
+    }
+
+    /// Status of the entries of all projects of the profile. Entries that fail the integrity
+    /// check are still described, so plain entries can be inspected without the token.
+    pub fn profile_status(&self, profile: &str, max_age: &u64) -> Vec<CacheStatus> {
+        let profile_directory = self.directory.join(profile);
+        let mut statuses = fs::read_dir(profile_directory)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|file| file.path())
+            .filter(|path| path.extension() == Some(OsStr::new("yaml")))
+            .map(|path| {
+                let cache_entry = fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|cache_entry| self.decode_unverified(&cache_entry).ok())
+                    .map(|(cache_entry, _)| cache_entry);
+                let integrity = cache_entry
+                    .as_ref()
+                    .and_then(|cache_entry| self.verify(cache_entry));
+                CacheStatus::new(profile.to_string(), path, cache_entry, integrity, max_age)
+            })
+            .collect::<Vec<CacheStatus>>();
+        statuses.sort_by(|a, b| a.project.cmp(&b.project));
+        statuses
+    }
+
+    pub fn profiles(&self) -> Vec<String> {
+        let mut profiles: Vec<String> = fs::read_dir(&self.directory)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        profiles.sort();
+        profiles
+    }
+
+    pub async fn get_or_revalidate<'b, 'c, RevalidateFn, ReturnValue, Fetched, Error>(
+        &'c self,
+        profile: &'c str,
         max_age: &u64,
+        fallback: &Fallback,
+        revalidation: &Revalidation,
         revalidate: RevalidateFn,
-    ) -> Option<CacheEntry>
+    ) -> Result<(CacheEntry<'b>, Option<BackgroundRevalidation<'c>>), Error>
     where
+        RevalidateFn: FnOnce() -> ReturnValue + 'c,
+        ReturnValue: Future<Output = Result<Fetched, Error>> + 'c,
+        Fetched: Into<FetchedSecrets<'b>>,
+        Error: Display + 'c,
+        'b: 'c,
+    {
+        if let Some(cache_entry) = self.get_fresh(profile, max_age) {
+            info!(message = format!("Using cached values for profile {:?}", profile));
+            return Ok((cache_entry, None));
+        }
+
+        if let Revalidation::Background { timeout } = *revalidation {
+            let stale_entry = self
+                .get(profile)
+                .filter(|cache_entry| self.is_usable_while_stale(cache_entry, fallback));
+            if let Some(cache_entry) = stale_entry {
+                info!(
+                    message = format!(
+                        "Using cached values for profile {:?} from {} ago, revalidating in the background",
+                        profile,
+                        format_duration(cache_entry.age())
+                    )
+                );
+                let background = self.revalidate_in_background(
+                    profile,
+                    *max_age,
+                    timeout,
+                    cache_entry.variables.clone(),
+                    revalidate,
+                );
+                return Ok((
+                    cache_entry,
+                    Some(BackgroundRevalidation(Box::pin(background))),
+                ));
+            }
+        }
+
+        let _lock = self.lock(profile).await;
+
+        let stale_entry = match self.get(profile) {
+            Some(cache_entry) if !self.is_stale(&cache_entry, max_age) => {
+                info!(
+                    message = format!(
+                        "Using cached values for profile {:?} revalidated by another process",
+                        profile
+                    )
+                );
+                return Ok((cache_entry, None));
+            }
+            stale_entry => stale_entry,
+        };
+
+        info!(message = format!("Revalidating cache for profile {:?}", profile));
+        match revalidate().await {
+            Ok(secrets) => Ok((self.set(profile, secrets), None)),
+            Err(err) => {
+                let stale_entry = stale_entry.filter(|cache_entry| self.matches(cache_entry));
+                Self::fall_back(profile, stale_entry, fallback, err)
+                    .map(|cache_entry| (cache_entry, None))
+            }
+        }
+    }
+
+    async fn revalidate_in_background<'b, RevalidateFn, ReturnValue, Fetched, Error>(
+        &self,
+        profile: &str,
+        max_age: u64,
+        timeout: Duration,
+        previous_variables: Secrets<'b>,
+        revalidate: RevalidateFn,
+    ) where
         RevalidateFn: FnOnce() -> ReturnValue,
-        ReturnValue: Future<Output = Secrets<'b>>,
+        ReturnValue: Future<Output = Result<Fetched, Error>>,
+        Fetched: Into<FetchedSecrets<'b>>,
+        Error: Display,
     {
-        match self.is_stale(profile, max_age) {
-            true => {
-                info!(message = format!("Revalidating cache for profile {:?}", profile));
-                let secrets = revalidate().await;
-                self.set(profile, secrets);
-                self.get(profile)
+        let result = tokio::time::timeout(timeout, async {
+            let _lock = self.lock(profile).await;
+            if self.get_fresh(profile, &max_age).is_some() {
+                return Ok(None);
             }
-            false => {
-                info!(message = format!("Using cached values for profile {:?}", profile));
-                self.get(profile)
+            revalidate().await.map(Some)
+        })
+        .await;
+
+        match result {
+            Ok(Ok(Some(fetched))) => {
+                let fetched = fetched.into();
+                let changed = fetched.secrets != previous_variables;
+                self.set(profile, fetched);
+                match changed {
+                    true => info!(
+                        message = format!(
+                            "Secrets of profile {:?} changed, the new values are used on the next run",
+                            profile
+                        )
+                    ),
+                    false => info!(
+                        message = format!("Secrets of profile {:?} are up to date", profile)
+                    ),
+                }
             }
+            Ok(Ok(None)) => info!(
+                message = format!(
+                    "Cache for profile {:?} was revalidated by another process",
+                    profile
+                )
+            ),
+            Ok(Err(err)) => warn!(
+                message = format!(
+                    "Failed to revalidate cache for profile {:?} in the background: {}",
+                    profile, err
+                )
+            ),
+            Err(_) => warn!(
+                message = format!(
+                    "Revalidating cache for profile {:?} in the background timed out after {}",
+                    profile,
+                    format_duration(timeout.as_secs())
+                )
+            ),
         }
     }
 
-    pub fn set(&self, profile: &str, variables: Secrets) {
-        let cache_file_path = self.get_cache_file_path(profile);
-        fs::create_dir_all(self.directory.clone()).unwrap();
-        let cache_entry = CacheEntry {
-            last_revalidation: SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .expect("SystemTime before UNIX EPOCH!")
-                .as_millis() as u64,
+    pub async fn refresh<'b, RevalidateFn, ReturnValue, Fetched, Error>(
+        &self,
+        profile: &str,
+        revalidate: RevalidateFn,
+    ) -> Result<CacheEntry<'b>, Error>
+    where
+        RevalidateFn: FnOnce() -> ReturnValue,
+        ReturnValue: Future<Output = Result<Fetched, Error>>,
+        Fetched: Into<FetchedSecrets<'b>>,
+    {
+        let _lock = self.lock(profile).await;
+        info!(message = format!("Revalidating cache for profile {:?}", profile));
+        Ok(self.set(profile, revalidate().await?))
+    }
+
+    fn get_fresh<'b>(&self, profile: &str, max_age: &u64) -> Option<CacheEntry<'b>> {
+        self.get(profile)
+            .filter(|cache_entry| !self.is_stale(cache_entry, max_age))
+    }
+
+    async fn lock(&self, profile: &str) -> Option<fs::File> {
+        if let Storage::None = self.storage {
+            return None;
+        }
+        self.create_profile_directory(profile).ok()?;
+        let lock_file_path = self.get_lock_file_path(profile);
+        tokio::task::spawn_blocking(move || lock_file(&lock_file_path))
+            .await
+            .ok()
+            .flatten()
+    }
+
+    fn fall_back<'b, Error: Display>(
+        profile: &str,
+        stale_entry: Option<CacheEntry<'b>>,
+        fallback: &Fallback,
+        err: Error,
+    ) -> Result<CacheEntry<'b>, Error> {
+        let (max_stale, cache_entry) = match (fallback, stale_entry) {
+            (Fallback::Stale { max_stale }, Some(cache_entry)) => (*max_stale, cache_entry),
+            _ => return Err(err),
+        };
+        let age = cache_entry.age();
+
+        if max_stale.is_some_and(|max_stale| age > max_stale) {
+            warn!(
+                message = format!(
+                    "Cached values for profile {:?} are {} old, exceeding the maximum staleness",
+                    profile,
+                    format_duration(age)
+                )
+            );
+            return Err(err);
+        }
+
+        warn!(
+            message = format!(
+                "Failed to revalidate cache for profile {:?}: {}. Using cached values from {} ago",
+                profile,
+                err,
+                format_duration(age)
+            )
+        );
+        Ok(cache_entry)
+    }
+
+    pub fn set<'b>(&self, profile: &str, fetched: impl Into<FetchedSecrets<'b>>) -> CacheEntry<'b> {
+        self.store(profile, fetched.into(), 0, false)
+    }
+
+    pub fn seed<'b>(&self, profile: &str, variables: Secrets<'b>, age: u64) -> CacheEntry<'b> {
+        info!(message = format!("Seeding cache for profile {:?}", profile));
+        self.store(profile, variables.into(), age, true)
+    }
+
+    fn store<'b>(
+        &self,
+        profile: &str,
+        fetched: FetchedSecrets<'b>,
+        age: u64,
+        seeded: bool,
+    ) -> CacheEntry<'b> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("SystemTime before UNIX EPOCH!")
+            .as_millis() as u64;
+        let mut cache_entry = CacheEntry {
+            last_revalidation: now.saturating_sub(age.saturating_mul(1000)),
             version: self.version.clone(),
-            variables,
+            identity: self.identity.clone(),
+            seeded,
+            variables: fetched.secrets,
+            metadata: fetched.metadata,
+            schema: SCHEMA_VERSION,
+            mac: None,
         };
-        let cache_entry = serde_yaml::to_string(&cache_entry).unwrap();
-        std::fs::write(cache_file_path, cache_entry).unwrap();
+        self.write_or_warn(profile, &mut cache_entry);
+        cache_entry
     }
 
+    /// Removes the entry of the project, so caches of other projects sharing the user location
+    /// and the profile name are kept
     pub fn clear(&self, profile: &str) {
         info!(message = format!("Clearing cache for profile {:?}", profile));
-        let cache_file_path = self.get_cache_file_path(profile);
-        let _ = fs::remove_file(cache_file_path);
+        let _ = fs::remove_file(self.get_cache_file_path(profile));
+        let _ = fs::remove_file(self.get_lock_file_path(profile));
+        let _ = fs::remove_file(self.get_legacy_cache_file_path(profile));
+        // Only succeeds once no other project has an entry for the profile
+        let _ = fs::remove_dir(self.directory.join(profile));
+    }
+
+    pub fn clear_all(&self) {
+        info!(message = "Clearing cache for all profiles");
+        let _ = fs::remove_dir_all(&self.directory);
+    }
+
+    pub fn prune(&self, profiles: &[&str]) -> Vec<String> {
+        let mut pruned = vec![];
+        for entry in fs::read_dir(&self.directory)
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = entry.path();
+            if path.is_dir() && profiles.contains(&name.as_str()) {
+                continue;
+            }
+            info!(message = format!("Pruning cache {:?}", name));
+            let _ = match path.is_dir() {
+                true => fs::remove_dir_all(&path),
+                false => fs::remove_file(&path),
+            };
+            pruned.push(name);
+        }
+        pruned
     }
 
     pub fn invalidate(&self, profile: &str) {
         info!(message = format!("Invalidating cache for profile {:?}", profile));
         if let Some(cache_entry) = self.get(profile) {
-            let cache_file_path = self.get_cache_file_path(profile);
-            fs::create_dir_all(self.directory.clone()).unwrap();
-            let cache_entry = CacheEntry {
+            let mut cache_entry = CacheEntry {
                 last_revalidation: 0,
                 version: self.version.clone(),
+                identity: cache_entry.identity,
+                seeded: cache_entry.seeded,
                 variables: cache_entry.variables,
+                metadata: cache_entry.metadata,
+                schema: SCHEMA_VERSION,
+                mac: None,
             };
-            let cache_entry = serde_yaml::to_string(&cache_entry).unwrap();
-            std::fs::write(cache_file_path, cache_entry).unwrap();
+            self.write_or_warn(profile, &mut cache_entry);
         }
     }
 
-    fn is_stale(&self, profile: &str, seconds: &u64) -> bool {
-        let cache_entry = self.get(profile);
-        match &cache_entry {
-            None => true,
-            Some(cache_entry) => {
-                is_date_older_than_n_seconds(cache_entry.last_revalidation, seconds)
-                    || self.version != &cache_entry.version
+    /// Writes the entry, a cache that can not be written, e.g. a read-only or full directory, is
+    /// only logged so the command still runs
+    fn write_or_warn(&self, profile: &str, cache_entry: &mut CacheEntry) {
+        if let Err(err) = self.write(profile, cache_entry) {
+            warn!(
+                message = format!(
+                    "Could not write cache for profile {:?}: {}, continuing without it",
+                    profile, err
+                )
+            );
+        }
+    }
+
+    fn write(&self, profile: &str, cache_entry: &mut CacheEntry) -> std::io::Result<()> {
+        if let Some(key) = &self.identity.integrity_key {
+            cache_entry.sign(key);
+        }
+        let cache_entry = serde_yaml::to_string(cache_entry).map_err(std::io::Error::other)?;
+        let cache_entry = match &self.storage {
+            Storage::None => return Ok(()),
+            Storage::Plain => cache_entry,
+            Storage::Encrypted(key) => serde_yaml::to_string(&EncryptedCacheEntry {
+                encrypted: STANDARD.encode(crypto::encrypt(key, cache_entry.as_bytes())),
+            })
+            .map_err(std::io::Error::other)?,
+        };
+        let cache_file_path = self.get_cache_file_path(profile);
+        let profile_directory = self.create_profile_directory(profile)?;
+        let mut file = NamedTempFile::new_in(&profile_directory)?;
+        file.write_all(cache_entry.as_bytes())?;
+        file.persist(cache_file_path)?;
+        Ok(())
+    }
+
+    /// Decodes a cache entry, migrating it to the current schema version if needed.
+    /// Returns the schema version the entry was migrated from, if any.
+    fn decode<'b>(&self, cache_entry: &str) -> Result<(CacheEntry<'b>, Option<u32>), &'static str> {
+        let (cache_entry, migrated_from) = self.decode_unverified(cache_entry)?;
+        match self.verify(&cache_entry) {
+            Some(false) => Err("integrity check failed"),
+            _ => Ok((cache_entry, migrated_from)),
+        }
+    }
+
+    fn decode_unverified<'b>(
+        &self,
+        cache_entry: &str,
+    ) -> Result<(CacheEntry<'b>, Option<u32>), &'static str> {
+        let cache_entry = match &self.storage {
+            Storage::Encrypted(key) => {
+                Self::decrypt(key, cache_entry).ok_or("could not decrypt entry")?
+            }
+            _ => cache_entry.to_string(),
+        };
+        let cache_entry: serde_yaml::Value =
+            serde_yaml::from_str(&cache_entry).map_err(|_| "could not parse entry")?;
+        let (cache_entry, migrated_from) = migrate(cache_entry)?;
+        let cache_entry: CacheEntry =
+            serde_yaml::from_value(cache_entry).map_err(|_| "could not parse entry")?;
+        Ok((cache_entry, migrated_from))
+    }
+
+    /// Checks the signature of the entry, `None` if the cache has no integrity key
+    fn verify(&self, cache_entry: &CacheEntry) -> Option<bool> {
+        self.identity
+            .integrity_key
+            .as_ref()
+            .map(|key| cache_entry.verify(key))
+    }
+
+    fn decrypt(key: &Key, cache_entry: &str) -> Option<String> {
+        let cache_entry: EncryptedCacheEntry = serde_yaml::from_str(cache_entry).ok()?;
+        let cache_entry = STANDARD.decode(cache_entry.encrypted).ok()?;
+        let cache_entry = crypto::decrypt(key, &cache_entry)?;
+        String::from_utf8(cache_entry).ok()
+    }
+
+    fn is_stale(&self, cache_entry: &CacheEntry, seconds: &u64) -> bool {
+        is_date_older_than_n_seconds(cache_entry.last_revalidation, seconds)
+            || !self.matches(cache_entry)
+    }
+
+    fn is_usable_while_stale(&self, cache_entry: &CacheEntry, fallback: &Fallback) -> bool {
+        match fallback {
+            Fallback::Strict => false,
+            Fallback::Stale { max_stale } => {
+                self.matches(cache_entry)
+                    && !max_stale.is_some_and(|max_stale| cache_entry.age() > max_stale)
             }
         }
     }
 
+    fn matches(&self, cache_entry: &CacheEntry) -> bool {
+        self.identity == cache_entry.identity
+    }
+
+    /// Creates the directory of the profile, restricting the cache root as well since it may
+    /// already exist with the default mode, e.g. the shared user location
+    fn create_profile_directory(&self, profile: &str) -> std::io::Result<PathBuf> {
+        let profile_directory = self.directory.join(profile);
+        create_private_dir_all(&self.directory)?;
+        create_private_dir_all(&profile_directory)?;
+        Ok(profile_directory)
+    }
+
     fn get_cache_file_path(&self, profile: &str) -> PathBuf {
-        let mut cache_file_path = self.directory.join(profile);
-        cache_file_path.set_extension("yaml");
-        cache_file_path
+        self.directory
+            .join(profile)
+            .join(format!("{}.yaml", self.identity.project_key()))
+    }
+
+    fn get_legacy_cache_file_path(&self, profile: &str) -> PathBuf {
+        self.directory.join(format!("{}.yaml", profile))
+    }
+
+    fn get_lock_file_path(&self, profile: &str) -> PathBuf {
+        self.directory
+            .join(profile)
+            .join(format!("{}.lock", self.identity.project_key()))
     }
 }
 
+/// Transforms a raw cache entry written with an older schema version into the current one.
+/// Migrations must not change signed fields, so entries still verify after migrating.
+fn migrate(
+    mut cache_entry: serde_yaml::Value,
+) -> Result<(serde_yaml::Value, Option<u32>), &'static str> {
+    let schema = match cache_entry.get("schema") {
+        Some(schema) => schema
+            .as_u64()
+            .and_then(|schema| u32::try_from(schema).ok())
+            .ok_or("could not parse entry")?,
+        None => 0,
+    };
+    if schema > SCHEMA_VERSION {
+        return Err("written by a newer version of bwenv");
+    }
+    if schema == SCHEMA_VERSION {
+        return Ok((cache_entry, None));
+    }
+
+    for from in schema..SCHEMA_VERSION {
+        match from {
+            // Entries written before schema versions were introduced only lack the field itself
+            0 => {}
+            // Metadata was added, entries without it default to none
+            1 => {}
+            _ => unreachable!("missing cache migration from schema version {}", from),
+        }
+    }
+
+    cache_entry
+        .as_mapping_mut()
+        .ok_or("could not parse entry")?
+        .insert("schema".into(), SCHEMA_VERSION.into());
+    Ok((cache_entry, Some(schema)))
+}
+
+/// Locks the given file exclusively, its directory must exist, see `create_profile_directory`
+fn lock_file(lock_file_path: &Path) -> Option<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.create(true).truncate(false).write(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let lock_file = options.open(lock_file_path).ok()?;
+    lock_file.lock_exclusive().ok()?;
+    Some(lock_file)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -147,7 +882,12 @@ mod tests {
     #[test]
     fn test_new() {
         let (temp_dir, version) = setup_test_environment();
-        let cache = Cache::new(temp_dir.clone(), &version);
+        let cache = Cache::new(
+            temp_dir.clone(),
+            &version,
+            Storage::Plain,
+            Identity::default(),
+        );
 
         assert_eq!(cache.directory, temp_dir.join("bwenv"));
         assert_eq!(cache.version, &version);
@@ -156,7 +896,7 @@ mod tests {
     #[tokio::test]
     async fn test_get_and_set() {
         let (temp_dir, version) = setup_test_environment();
-        let cache = Cache::new(temp_dir, &version);
+        let cache = Cache::new(temp_dir, &version, Storage::Plain, Identity::default());
         let profile = "test_profile";
 
         let variables: HashMap<Cow<str>, Cow<str>> =
@@ -172,7 +912,12 @@ mod tests {
     #[tokio::test]
     async fn test_clear_and_invalidate() {
         let (temp_dir, version) = setup_test_environment();
-        let cache = Cache::new(temp_dir.clone(), &version);
+        let cache = Cache::new(
+            temp_dir.clone(),
+            &version,
+            Storage::Plain,
+            Identity::default(),
+        );
         let profile = "test_profile";
 
         let variables: HashMap<Cow<str>, Cow<str>> =
@@ -182,8 +927,17 @@ mod tests {
         cache.set(profile, secrets.clone());
         assert!(cache.get(profile).is_some());
 
+        let other_project = Cache::new(
+            temp_dir.clone(),
+            &version,
+            Storage::Plain,
+            Identity::new(vec![String::from("other")], ""),
+        );
+        other_project.set(profile, secrets.clone());
+
         cache.clear(profile);
         assert!(cache.get(profile).is_none());
+        assert!(other_project.get(profile).is_some());
 
         cache.set(profile, secrets);
         cache.invalidate(profile);
@@ -192,4 +946,552 @@ mod tests {
             .expect("Failed to get cache entry after invalidation");
         assert_eq!(cache_entry.last_revalidation, 0);
     }
+
+    #[tokio::test]
+    async fn test_encrypted_storage() {
+        let (temp_dir, version) = setup_test_environment();
+        let cache = Cache::new(
+            temp_dir.clone(),
+            &version,
+            Storage::encrypted(b"access-token"),
+            Identity::default(),
+        );
+        let profile = "test_profile";
+
+        let variables: HashMap<Cow<str>, Cow<str>> = [("key".into(), "secret_value".into())]
+            .iter()
+            .cloned()
+            .collect();
+        let secrets = Secrets(variables);
+
+        cache.set(profile, secrets.clone());
+
+        let raw = fs::read_to_string(cache.get_cache_file_path(profile)).unwrap();
+        assert!(!raw.contains("secret_value"));
+
+        let cache_entry = cache.get(profile).expect("Failed to get cache entry");
+        assert_eq!(cache_entry.variables, secrets);
+
+        let other_cache = Cache::new(
+            temp_dir.clone(),
+            &version,
+            Storage::encrypted(b"other"),
+            Identity::default(),
+        );
+        assert!(other_cache.get(profile).is_none());
+
+        let plain_cache = Cache::new(temp_dir, &version, Storage::Plain, Identity::default());
+        assert!(plain_cache.get(profile).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_no_storage() {
+        let (temp_dir, version) = setup_test_environment();
+        let cache = Cache::new(temp_dir, &version, Storage::None, Identity::default());
+        let profile = "test_profile";
+
+        let variables: HashMap<Cow<str>, Cow<str>> =
+            [("key".into(), "value".into())].iter().cloned().collect();
+        let secrets = Secrets(variables);
+
+        let (cache_entry, _) = cache
+            .get_or_revalidate(
+                profile,
+                &86400,
+                &Fallback::Strict,
+                &Revalidation::Blocking,
+                || async { Ok::<_, String>(secrets.clone()) },
+            )
+            .await
+            .expect("Failed to revalidate");
+        assert_eq!(cache_entry.variables, secrets);
+        assert!(!cache.get_cache_file_path(profile).exists());
+        assert!(cache.get(profile).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fallback_to_stale_entry() {
+        let (temp_dir, version) = setup_test_environment();
+        let cache = Cache::new(temp_dir, &version, Storage::Plain, Identity::default());
+        let profile = "test_profile";
+
+        let variables: HashMap<Cow<str>, Cow<str>> =
+            [("key".into(), "value".into())].iter().cloned().collect();
+        let secrets = Secrets(variables);
+
+        cache.set(profile, secrets.clone());
+        let mut cache_entry = cache.get(profile).unwrap();
+        cache_entry.last_revalidation -= 3_600_000;
+        cache.write(profile, &mut cache_entry).unwrap();
+
+        let failing = || async { Err::<Secrets, _>(String::from("network failure")) };
+
+        let (cache_entry, _) = cache
+            .get_or_revalidate(
+                profile,
+                &60,
+                &Fallback::Stale { max_stale: None },
+                &Revalidation::Blocking,
+                failing,
+            )
+            .await
+            .expect("Failed to fall back to stale entry");
+        assert_eq!(cache_entry.variables, secrets);
+
+        let result = cache
+            .get_or_revalidate(
+                profile,
+                &60,
+                &Fallback::Stale {
+                    max_stale: Some(60),
+                },
+                &Revalidation::Blocking,
+                failing,
+            )
+            .await;
+        assert_eq!(result.unwrap_err(), "network failure");
+
+        let result = cache
+            .get_or_revalidate(
+                profile,
+                &60,
+                &Fallback::Strict,
+                &Revalidation::Blocking,
+                failing,
+            )
+            .await;
+        assert_eq!(result.unwrap_err(), "network failure");
+    }
+
+    #[tokio::test]
+    async fn test_background_revalidation() {
+        let (temp_dir, version) = setup_test_environment();
+        let cache = Cache::new(temp_dir, &version, Storage::Plain, Identity::default());
+        let profile = "test_profile";
+        let fallback = Fallback::Stale { max_stale: None };
+        let revalidation = Revalidation::Background {
+            timeout: Duration::from_millis(100),
+        };
+
+        cache.set(profile, Secrets::default());
+        let mut cache_entry = cache.get(profile).unwrap();
+        cache_entry.last_revalidation -= 3_600_000;
+        cache.write(profile, &mut cache_entry).unwrap();
+
+        let (cache_entry, background) = cache
+            .get_or_revalidate(profile, &60, &fallback, &revalidation, || async {
+                Ok::<_, String>(Secrets(
+                    [("key".into(), "value".into())].iter().cloned().collect(),
+                ))
+            })
+            .await
+            .expect("Failed to use stale entry");
+        assert!(cache_entry.variables.is_empty());
+        background.expect("Missing background revalidation").await;
+        assert_eq!(cache.get(profile).unwrap().variables.len(), 1);
+
+        cache.invalidate(profile);
+        let (cache_entry, background) = cache
+            .get_or_revalidate(profile, &60, &fallback, &revalidation, || async {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                Ok::<_, String>(Secrets::default())
+            })
+            .await
+            .expect("Failed to use stale entry");
+        assert_eq!(cache_entry.variables.len(), 1);
+        background.expect("Missing background revalidation").await;
+        assert_eq!(cache.get(profile).unwrap().last_revalidation, 0);
+
+        let (_, background) = cache
+            .get_or_revalidate(profile, &60, &Fallback::Strict, &revalidation, || async {
+                Ok::<_, String>(Secrets::default())
+            })
+            .await
+            .expect("Failed to revalidate");
+        assert!(background.is_none());
+        assert!(cache.get(profile).unwrap().variables.is_empty());
+    }
+
+    #[test]
+    fn test_waits_for_concurrent_revalidation() {
+        let (temp_dir, version) = setup_test_environment();
+        let cache = Cache::new(
+            temp_dir.clone(),
+            &version,
+            Storage::Plain,
+            Identity::default(),
+        );
+        let profile = "test_profile";
+
+        cache.create_profile_directory(profile).unwrap();
+        let lock = lock_file(&cache.get_lock_file_path(profile)).unwrap();
+        let revalidating_process = std::thread::spawn(move || {
+            let version = Version::parse("1.0.0").unwrap();
+            let cache = Cache::new(temp_dir, &version, Storage::Plain, Identity::default());
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            cache.set(
+                "test_profile",
+                Secrets([("key".into(), "value".into())].iter().cloned().collect()),
+            );
+            drop(lock);
+        });
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let (cache_entry, _) = runtime
+            .block_on(cache.get_or_revalidate(
+                profile,
+                &60,
+                &Fallback::Strict,
+                &Revalidation::Blocking,
+                || async { Err::<Secrets, _>("revalidated twice") },
+            ))
+            .expect("Failed to reuse concurrently revalidated entry");
+        revalidating_process.join().unwrap();
+
+        assert_eq!(cache_entry.variables.get("key").unwrap(), "value");
+    }
+
+    #[tokio::test]
+    async fn test_write_leaves_no_temporary_files() {
+        let (temp_dir, version) = setup_test_environment();
+        let cache = Cache::new(temp_dir, &version, Storage::Plain, Identity::default());
+
+        cache.set("test_profile", Secrets::default());
+        cache.set("test_profile", Secrets::default());
+
+        let profile_directory = cache.directory.join("test_profile");
+        let files: Vec<_> = fs::read_dir(profile_directory).unwrap().collect();
+        assert_eq!(files.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_identity_mismatch() {
+        let (temp_dir, version) = setup_test_environment();
+        let identity = Identity::new(vec![String::from("project")], "token");
+        let cache = Cache::new(temp_dir.clone(), &version, Storage::Plain, identity);
+        let profile = "test_profile";
+
+        let variables: HashMap<Cow<str>, Cow<str>> =
+            [("key".into(), "value".into())].iter().cloned().collect();
+        cache.set(profile, Secrets(variables));
+
+        let other_token = Identity::new(vec![String::from("project")], "other_token");
+        let other_token_cache = Cache::new(temp_dir.clone(), &version, Storage::Plain, other_token);
+        let result = other_token_cache
+            .get_or_revalidate(
+                profile,
+                &86400,
+                &Fallback::Stale { max_stale: None },
+                &Revalidation::Blocking,
+                || async { Err::<Secrets, _>("network failure") },
+            )
+            .await;
+        assert_eq!(result.unwrap_err(), "network failure");
+
+        let other_project = Identity::new(vec![String::from("other_project")], "token");
+        let other_project_cache = Cache::new(temp_dir, &version, Storage::Plain, other_project);
+        assert!(other_project_cache.get(profile).is_none());
+        other_project_cache.set(profile, Secrets::default());
+
+        assert_eq!(
+            cache.get(profile).unwrap().variables.get("key").unwrap(),
+            "value"
+        );
+        assert!(other_project_cache
+            .get(profile)
+            .unwrap()
+            .variables
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_status_clear_all_and_prune() {
+        let (temp_dir, version) = setup_test_environment();
+        let cache = Cache::new(
+            temp_dir,
+            &version,
+            Storage::Plain,
+            Identity::new(vec![String::from("project")], "token"),
+        );
+
+        let variables: HashMap<Cow<str>, Cow<str>> =
+            [("key".into(), "value".into())].iter().cloned().collect();
+        cache.set("default", Secrets(variables));
+        cache.set("removed", Secrets::default());
+        fs::write(cache.directory.join("legacy.yaml"), "legacy").unwrap();
+        fs::write(cache.directory.join("removed").join("broken.yaml"), "-").unwrap();
+
+        let statuses = cache.status(&86400);
+        assert_eq!(statuses.len(), 3);
+        assert_eq!(statuses[0].profile, "default");
+        assert_eq!(statuses[0].project, "project");
+        assert_eq!(statuses[0].keys, Some(1));
+        assert!(statuses[0].readable);
+        assert!(statuses[0].revalidates_in.unwrap() > 86000);
+        assert_eq!(statuses[0].integrity, Some(true));
+        assert_eq!(statuses[1].project, "broken");
+        assert!(!statuses[1].readable);
+
+        let without_token = Cache::new(
+            cache.directory.parent().unwrap().to_path_buf(),
+            &version,
+            Storage::Plain,
+            Identity::default(),
+        );
+        let statuses = without_token.profile_status("default", &86400);
+        assert!(statuses[0].readable);
+        assert_eq!(statuses[0].keys, Some(1));
+        assert_eq!(statuses[0].integrity, None);
+
+        let pruned = cache.prune(&["default"]);
+        assert_eq!(pruned.len(), 2);
+        assert_eq!(cache.profiles(), vec![String::from("default")]);
+        assert!(!cache.directory.join("legacy.yaml").exists());
+
+        cache.clear_all();
+        assert!(cache.profiles().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_seed() {
+        let (temp_dir, version) = setup_test_environment();
+        let cache = Cache::new(temp_dir, &version, Storage::Plain, Identity::default());
+        let profile = "test_profile";
+
+        let variables: HashMap<Cow<str>, Cow<str>> =
+            [("key".into(), "value".into())].iter().cloned().collect();
+        cache.seed(profile, Secrets(variables), 3600);
+
+        let cache_entry = cache.get(profile).expect("Failed to get seeded entry");
+        assert!(cache_entry.seeded);
+        assert!((3600..=3601).contains(&cache_entry.age()));
+        assert!(cache.status(&86400)[0].seeded);
+
+        cache.set(profile, Secrets::default());
+        assert!(!cache.get(profile).unwrap().seeded);
+    }
+
+    #[tokio::test]
+    async fn test_schema_migration() {
+        let (temp_dir, version) = setup_test_environment();
+        let identity = Identity::new(vec![String::from("project")], "token");
+        let cache = Cache::new(temp_dir.clone(), &version, Storage::Plain, identity);
+        let profile = "test_profile";
+
+        let variables: HashMap<Cow<str>, Cow<str>> =
+            [("key".into(), "value".into())].iter().cloned().collect();
+        cache.set(profile, Secrets(variables));
+
+        let cache_file_path = cache.get_cache_file_path(profile);
+        let raw = fs::read_to_string(&cache_file_path).unwrap();
+        fs::write(
+            &cache_file_path,
+            raw.replace(&format!("schema: {}\n", SCHEMA_VERSION), ""),
+        )
+        .unwrap();
+
+        let upgraded_version = Version::parse("1.0.1").unwrap();
+        let upgraded_cache = Cache::new(
+            temp_dir,
+            &upgraded_version,
+            Storage::Plain,
+            Identity::new(vec![String::from("project")], "token"),
+        );
+        let (cache_entry, _) = upgraded_cache
+            .get_or_revalidate(
+                profile,
+                &86400,
+                &Fallback::Strict,
+                &Revalidation::Blocking,
+                || async { Err::<Secrets, _>("revalidated after upgrade") },
+            )
+            .await
+            .expect("Failed to migrate cache entry");
+        assert_eq!(cache_entry.schema, SCHEMA_VERSION);
+        assert_eq!(cache_entry.variables.get("key").unwrap(), "value");
+
+        let raw = fs::read_to_string(&cache_file_path).unwrap();
+        assert!(raw.contains(&format!("schema: {}", SCHEMA_VERSION)));
+
+        fs::write(
+            &cache_file_path,
+            raw.replace(
+                &format!("schema: {}", SCHEMA_VERSION),
+                &format!("schema: {}", SCHEMA_VERSION + 1),
+            ),
+        )
+        .unwrap();
+        assert!(upgraded_cache.get(profile).is_none());
+    }
+
+    #[test]
+    fn test_stores_metadata() {
+        let (temp_dir, version) = setup_test_environment();
+        let identity = Identity::new(vec![String::from("project")], "token");
+        let cache = Cache::new(temp_dir, &version, Storage::Plain, identity);
+        let profile = "test_profile";
+
+        let metadata = crate::config_yaml::SecretMetadata {
+            id: String::from("0e1f2a3b-4c5d-6e7f-8091-a2b3c4d5e6f7"),
+            note: Some(String::from("Used by the payment service")),
+            created: Some(String::from("2024-03-01T12:00:00+00:00")),
+            revised: Some(String::from("2024-04-01T12:00:00+00:00")),
+        };
+        cache.set(
+            profile,
+            FetchedSecrets {
+                secrets: Secrets([("key".into(), "value".into())].into_iter().collect()),
+                metadata: [(String::from("key"), metadata.clone())]
+                    .into_iter()
+                    .collect(),
+            },
+        );
+        assert_eq!(cache.get(profile).unwrap().metadata["key"], metadata);
+
+        cache.invalidate(profile);
+        assert_eq!(cache.get(profile).unwrap().metadata["key"], metadata);
+
+        let cache_file_path = cache.get_cache_file_path(profile);
+        let raw = fs::read_to_string(&cache_file_path).unwrap();
+        fs::write(
+            &cache_file_path,
+            raw.replace("payment service", "billing service"),
+        )
+        .unwrap();
+        assert!(cache.get(profile).is_none());
+    }
+
+    #[test]
+    fn test_set_without_writable_directory() {
+        let (temp_dir, version) = setup_test_environment();
+        let cache = Cache::new(temp_dir, &version, Storage::Plain, Identity::default());
+        // A file in place of the cache directory fails even for privileged users
+        fs::write(&cache.directory, "").unwrap();
+
+        let variables: HashMap<Cow<str>, Cow<str>> =
+            [("key".into(), "value".into())].iter().cloned().collect();
+        let cache_entry = cache.set("test_profile", Secrets(variables));
+        assert_eq!(cache_entry.variables.get("key").unwrap(), "value");
+        assert!(cache.get("test_profile").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_revalidates_legacy_layout() {
+        let (temp_dir, version) = setup_test_environment();
+        let identity = Identity::new(vec![String::from("project")], "token");
+        let cache = Cache::new(temp_dir, &version, Storage::Plain, identity);
+        let profile = "default";
+
+        // Written by the baseline version, before entries were stored per project
+        let last_revalidation = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64
+            - 3_600_000;
+        let legacy_cache_file_path = cache.directory.join("default.yaml");
+        fs::create_dir_all(&cache.directory).unwrap();
+        fs::write(
+            &legacy_cache_file_path,
+            format!(
+                "---\nlast_revalidation: {}\nvariables:\n  KEY: injected\nversion: 0.1.0\n",
+                last_revalidation
+            ),
+        )
+        .unwrap();
+
+        let (cache_entry, _) = cache
+            .get_or_revalidate(
+                profile,
+                &86400,
+                &Fallback::Stale { max_stale: None },
+                &Revalidation::Blocking,
+                || async {
+                    Ok::<_, String>(Secrets(
+                        [("KEY".into(), "value".into())].iter().cloned().collect(),
+                    ))
+                },
+            )
+            .await
+            .expect("Failed to revalidate");
+        assert_eq!(cache_entry.variables.get("KEY").unwrap(), "value");
+        assert!(!legacy_cache_file_path.exists());
+        assert_eq!(
+            cache.get(profile).unwrap().variables.get("KEY").unwrap(),
+            "value"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh() {
+        let (temp_dir, version) = setup_test_environment();
+        let cache = Cache::new(temp_dir, &version, Storage::Plain, Identity::default());
+        let profile = "test_profile";
+
+        cache.set(profile, Secrets::default());
+        let cache_entry = cache
+            .refresh(profile, || async {
+                Ok::<_, String>(Secrets(
+                    [("key".into(), "value".into())].iter().cloned().collect(),
+                ))
+            })
+            .await
+            .expect("Failed to refresh");
+        assert_eq!(cache_entry.variables.get("key").unwrap(), "value");
+        assert_eq!(cache.get(profile).unwrap().variables.len(), 1);
+
+        let result = cache
+            .refresh(profile, || async { Err::<Secrets, _>("network failure") })
+            .await;
+        assert_eq!(result.unwrap_err(), "network failure");
+        assert_eq!(cache.get(profile).unwrap().variables.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_tampered_entry_is_rejected() {
+        let (temp_dir, version) = setup_test_environment();
+        let identity = Identity::new(vec![String::from("project")], "token");
+        let cache = Cache::new(temp_dir, &version, Storage::Plain, identity);
+        let profile = "test_profile";
+
+        let variables: HashMap<Cow<str>, Cow<str>> =
+            [("key".into(), "value".into())].iter().cloned().collect();
+        cache.set(profile, Secrets(variables));
+        assert!(cache.get(profile).is_some());
+
+        let cache_file_path = cache.get_cache_file_path(profile);
+        let raw = fs::read_to_string(&cache_file_path).unwrap();
+        fs::write(&cache_file_path, raw.replace("value", "evil")).unwrap();
+        assert!(cache.get(profile).is_none());
+
+        let (cache_entry, _) = cache
+            .get_or_revalidate(
+                profile,
+                &86400,
+                &Fallback::Strict,
+                &Revalidation::Blocking,
+                || async { Ok::<_, String>(Secrets::default()) },
+            )
+            .await
+            .expect("Failed to revalidate tampered entry");
+        assert!(cache_entry.variables.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_entry_is_rejected() {
+        let (temp_dir, version) = setup_test_environment();
+        let unsigned_cache = Cache::new(
+            temp_dir.clone(),
+            &version,
+            Storage::Plain,
+            Identity::default(),
+        );
+        unsigned_cache.set("test_profile", Secrets::default());
+
+        let identity = Identity::new(vec![], "token");
+        let cache = Cache::new(temp_dir, &version, Storage::Plain, identity);
+        assert!(cache.get("test_profile").is_none());
+    }
 }
@@ -6,13 +6,30 @@ use std::path::{Path, PathBuf};
 #[derive(Debug)]
 pub enum LocalConfig {
     Yaml(PathBuf),
+    Json(PathBuf),
     Toml(PathBuf),
 }
 
 impl LocalConfig {
+    pub fn from_path(path: &Path) -> anyhow::Result<LocalConfig, ConfigError> {
+        if !path.is_file() {
+            return Err(ConfigError::NotFound);
+        }
+
+        let path = path.to_path_buf();
+
+        match path.extension().and_then(std::ffi::OsStr::to_str) {
+            Some("yaml") | Some("yml") => Ok(LocalConfig::Yaml(path)),
+            Some("json") => Ok(LocalConfig::Json(path)),
+            Some("toml") => Ok(LocalConfig::Toml(path)),
+            _ => Err(ConfigError::UnknownFormat),
+        }
+    }
+
     pub fn as_pathbuf(&self) -> &PathBuf {
         match self {
             Self::Yaml(path) => path,
+            Self::Json(path) => path,
             Self::Toml(path) => path,
         }
     }
@@ -27,6 +44,12 @@ pub fn find_local_config(cwd: Option<&Path>) -> anyhow::Result<LocalConfig, Conf
         return Ok(LocalConfig::Yaml(path));
     }
 
+    let json_config = find_up("bwenv.json", None, cwd);
+
+    if let Some(path) = json_config {
+        return Ok(LocalConfig::Json(path));
+    }
+
     let toml_config = find_up("bwenv.toml", None, cwd);
 
     if let Some(path) = toml_config {
@@ -74,6 +97,40 @@ mod tests {
         assert!(matches!(config, LocalConfig::Toml(_)));
     }
 
+    #[test]
+    fn finds_json_config_in_current_dir() {
+        let temp_dir = tempdir().unwrap();
+        create_config_file(temp_dir.path(), "bwenv.json");
+
+        let result = find_local_config(Some(temp_dir.path()));
+        assert!(matches!(result, Ok(LocalConfig::Json(_))));
+    }
+
+    #[test]
+    fn from_path_detects_format_by_extension() {
+        let temp_dir = tempdir().unwrap();
+        create_config_file(temp_dir.path(), "custom.json");
+        create_config_file(temp_dir.path(), "custom.yml");
+        create_config_file(temp_dir.path(), "custom.txt");
+
+        assert!(matches!(
+            LocalConfig::from_path(&temp_dir.path().join("custom.json")),
+            Ok(LocalConfig::Json(_))
+        ));
+        assert!(matches!(
+            LocalConfig::from_path(&temp_dir.path().join("custom.yml")),
+            Ok(LocalConfig::Yaml(_))
+        ));
+        assert!(matches!(
+            LocalConfig::from_path(&temp_dir.path().join("custom.txt")),
+            Err(ConfigError::UnknownFormat)
+        ));
+        assert!(matches!(
+            LocalConfig::from_path(&temp_dir.path().join("missing.yaml")),
+            Err(ConfigError::NotFound)
+        ));
+    }
+
     #[test]
     fn config_not_found_returns_error() {
         let temp_dir = tempdir().unwrap();
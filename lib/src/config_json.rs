@@ -0,0 +1,123 @@
+use anyhow::anyhow;
+use format_serde_error::{ErrorTypes, SerdeError};
+use jsonschema::JSONSchema;
+use schemars::schema_for;
+use std::{fs, path::Path};
+use tracing::info;
+
+use crate::config_yaml;
+
+pub fn parse_config_file<'a, P: AsRef<Path>>(
+    file_path: P,
+) -> Result<config_yaml::Config<'a>, anyhow::Error> {
+    info!(message = format!("Using configuration file at {:?}", file_path.as_ref()));
+    let raw = fs::read_to_string(file_path.as_ref()).map_err(|err| {
+        anyhow!(
+            "Could not read configuration file {:?}: {}",
+            file_path.as_ref(),
+            err
+        )
+    })?;
+
+    let value = serde_json::from_str::<serde_json::Value>(&raw)
+        .map_err(|err| SerdeError::new(raw.to_string(), ErrorTypes::Json(err)))?;
+    validate(&value)?;
+
+    Ok(serde_json::from_str::<config_yaml::Config>(&raw)
+        .map_err(|err| SerdeError::new(raw.to_string(), ErrorTypes::Json(err)))?)
+}
+
+fn validate(value: &serde_json::Value) -> Result<(), anyhow::Error> {
+    let schema = serde_json::to_value(schema_for!(config_yaml::Config))?;
+    let schema = JSONSchema::compile(&schema)
+        .map_err(|err| anyhow!("Failed to compile configuration schema: {}", err))?;
+
+    if let Err(errors) = schema.validate(value) {
+        let errors = errors
+            .map(|err| format!("  {}: {}", err.instance_path, err))
+            .collect::<Vec<String>>()
+            .join("\n");
+        return Err(anyhow!(
+            "Configuration file does not match the schema:\n{}",
+            errors
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::Builder;
+
+    fn write_config(content: &str) -> tempfile::NamedTempFile {
+        let mut temp_file = Builder::new().suffix(".json").tempfile().unwrap();
+        write!(temp_file, "{}", content).unwrap();
+        temp_file
+    }
+
+    #[test]
+    fn test_parse_config_file_success() {
+        let temp_file = write_config(
+            r#"{
+  "version": "1.0.0",
+  "cache": { "path": "/tmp/cache", "max-age": 3600 },
+  "global": { "overrides": { "global_key": "global_value" } },
+  "profiles": {
+    "default": { "project-id": "test_project" }
+  }
+}"#,
+        );
+
+        let config = parse_config_file(temp_file.path()).unwrap();
+        assert_eq!(*config.cache.max_age, 3600);
+
+        let eval_result = config.evaluate("default").unwrap();
+        assert_eq!(eval_result.project_id, "test_project");
+        assert_eq!(
+            eval_result.overrides.get("global_key").unwrap(),
+            "global_value"
+        );
+    }
+
+    #[test]
+    fn test_parse_config_file_schema_violation() {
+        let temp_file = write_config(
+            r#"{
+  "version": "1.0.0",
+  "cache": {},
+  "profiles": {
+    "default": { "overrides": {} }
+  }
+}"#,
+        );
+
+        let result = parse_config_file(temp_file.path());
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("does not match the schema"));
+    }
+
+    #[test]
+    fn test_parse_config_file_missing() {
+        let result = parse_config_file("/nonexistent/bwenv.json");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Could not read configuration file"));
+    }
+
+    #[test]
+    fn test_schema_matches_committed_schema() {
+        // schema.json is regenerated by the build script of the binary, validation must not
+        // drift from the published schema
+        let committed: serde_json::Value =
+            serde_json::from_str(include_str!("../../schema.json")).unwrap();
+        let generated = serde_json::to_value(schema_for!(config_yaml::Config)).unwrap();
+        assert_eq!(generated, committed);
+    }
+}
@@ -1,12 +1,11 @@
 use anyhow::anyhow;
 use format_serde_error::{ErrorTypes, SerdeError};
 use serde::Deserialize;
-use std::{collections::BTreeMap, fs::File, io::Read, path::Path};
+use std::{collections::BTreeMap, fs, path::Path};
 use tracing::info;
 
 use crate::config_yaml::{self, Profiles};
 
-use crate::error::ConfigError;
 use crate::schema_types::VersionReq;
 
 #[derive(Debug, Deserialize, Clone)]
@@ -40,6 +39,11 @@ fn convert_toml_profile_to_yaml_profile(toml_profile: Profile<'_>) -> config_yam
     config_yaml::Profile {
         project_id: toml_profile.project.unwrap(),
         overrides: toml_profile.r#override,
+        protected: false,
+        server: config_yaml::Server::default(),
+        provider: config_yaml::ProviderKind::Bitwarden,
+        token_env: None,
+        token_command: None,
     }
 }
 
@@ -85,7 +89,11 @@ impl Config<'_> {
             cache: config_yaml::Cache {
                 path: config_yaml::CachePath(self.cache.path.clone()),
                 max_age: config_yaml::CacheMaxAge(*self.cache.max_age),
+                ..Default::default()
             },
+            server: config_yaml::Server::default(),
+            fetch: config_yaml::Fetch::default(),
+            token_command: None,
         }
     }
 }
@@ -94,11 +102,13 @@ fn parse_config_file<'a, P: AsRef<Path>>(file_path: P) -> Result<Config<'a>, any
     if let Some(path) = file_path.as_ref().to_str() {
         info!(message = format!("Using configuration file at {:?}", path));
     }
-    let mut raw = String::new();
-    let mut file = File::open(file_path)
-        .map_err(|_| ConfigError::Read)
-        .unwrap();
-    let _ = file.read_to_string(&mut raw);
+    let raw = fs::read_to_string(file_path.as_ref()).map_err(|err| {
+        anyhow!(
+            "Could not read configuration file {:?}: {}",
+            file_path.as_ref(),
+            err
+        )
+    })?;
 
     Ok(toml::from_str::<Config>(&raw)
         .map_err(|err| SerdeError::new(raw.to_string(), ErrorTypes::Toml(err)))?)
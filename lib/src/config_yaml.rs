@@ -5,15 +5,14 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Deserializer, Serialize};
 use std::{
     borrow::Cow,
-    collections::HashMap,
-    fs::File,
-    io::Read,
+    collections::{BTreeMap, HashMap},
+    fs,
     path::{Path, PathBuf},
 };
 use tabular::{Row, Table};
 use tracing::info;
 
-use crate::{error::ConfigError, schema_types::VersionReq};
+use crate::{error::ConfigError, schema_types::VersionReq, token};
 
 fn deserialize_null_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
 where
@@ -33,6 +32,33 @@ impl Default for CacheMaxAge {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Deref)]
+pub struct CacheRevalidationTimeout(pub u64);
+
+impl Default for CacheRevalidationTimeout {
+    fn default() -> Self {
+        CacheRevalidationTimeout(30)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema, Deref)]
+pub struct FetchBatchSize(pub usize);
+
+impl Default for FetchBatchSize {
+    fn default() -> Self {
+        FetchBatchSize(100)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema, Deref)]
+pub struct FetchConcurrency(pub usize);
+
+impl Default for FetchConcurrency {
+    fn default() -> Self {
+        FetchConcurrency(4)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, JsonSchema, Deref)]
 pub struct CachePath(pub PathBuf);
 
@@ -42,6 +68,125 @@ impl Default for CachePath {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheMode {
+    #[default]
+    Plain,
+    Encrypted,
+    None,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheFallback {
+    #[default]
+    Stale,
+    Strict,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheRevalidation {
+    #[default]
+    Blocking,
+    Background,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheLocation {
+    #[default]
+    Project,
+    User,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    Bitwarden,
+    File,
+    Vault,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerRegion {
+    Us,
+    Eu,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServerUrls {
+    pub identity_url: String,
+    pub api_url: String,
+}
+
+impl ServerRegion {
+    pub fn urls(&self) -> ServerUrls {
+        let domain = match self {
+            ServerRegion::Us => "bitwarden.com",
+            ServerRegion::Eu => "bitwarden.eu",
+        };
+        ServerUrls {
+            identity_url: format!("https://identity.{}", domain),
+            api_url: format!("https://api.{}", domain),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema)]
+pub struct Server {
+    #[serde(default)]
+    #[schemars(
+        title = "Server Region",
+        description = "Region of the Bitwarden cloud: us or eu, defaults to us"
+    )]
+    pub region: Option<ServerRegion>,
+
+    #[serde(default, rename = "identity-url")]
+    #[schemars(
+        title = "Server Identity URL",
+        description = "URL of the Bitwarden identity service, e.g. of a self-hosted server, takes precedence over the region"
+    )]
+    pub identity_url: Option<String>,
+
+    #[serde(default, rename = "api-url")]
+    #[schemars(
+        title = "Server API URL",
+        description = "URL of the Bitwarden API, e.g. of a self-hosted server, takes precedence over the region"
+    )]
+    pub api_url: Option<String>,
+}
+
+impl Server {
+    /// Returns the server with the options set in `other` taking precedence. A region set in
+    /// `other` replaces the URLs of `self`, as they would otherwise take precedence over it.
+    pub fn merge(&self, other: &Server) -> Server {
+        let inherited = match other.region {
+            Some(_) => Server::default(),
+            None => self.clone(),
+        };
+        Server {
+            region: other.region.or(inherited.region),
+            identity_url: other.identity_url.clone().or(inherited.identity_url),
+            api_url: other.api_url.clone().or(inherited.api_url),
+        }
+    }
+
+    pub fn urls(&self) -> ServerUrls {
+        let region_urls = self.region.unwrap_or(ServerRegion::Us).urls();
+        ServerUrls {
+            identity_url: self
+                .identity_url
+                .clone()
+                .unwrap_or(region_urls.identity_url),
+            api_url: self.api_url.clone().unwrap_or(region_urls.api_url),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Default, JsonSchema)]
 pub struct Cache {
     #[serde(default)]
@@ -51,12 +196,78 @@ pub struct Cache {
     )]
     pub path: CachePath,
 
+    #[serde(default)]
+    #[schemars(
+        title = "Cache Location",
+        description = "Location of the local secrets cache: project uses the cache path, user uses the cache directory of the user shared by all clones of the project"
+    )]
+    pub location: CacheLocation,
+
     #[serde(default, rename = "max-age")]
     #[schemars(
         title = "Cache Max Age",
         description = "Maximum age of the local secrets cache in seconds"
     )]
     pub max_age: CacheMaxAge,
+
+    #[serde(default)]
+    #[schemars(
+        title = "Cache Mode",
+        description = "How secrets are stored in the local secrets cache: plain, encrypted or none"
+    )]
+    pub mode: CacheMode,
+
+    #[serde(default, rename = "key-file")]
+    #[schemars(
+        title = "Cache Key File",
+        description = "Path to a key file used to encrypt the local secrets cache relative to the project root, defaults to a key derived from the access token"
+    )]
+    pub key_file: Option<PathBuf>,
+
+    #[serde(default)]
+    #[schemars(
+        title = "Cache Fallback",
+        description = "Behavior if revalidating the local secrets cache fails: stale uses the cached values, strict fails"
+    )]
+    pub fallback: CacheFallback,
+
+    #[serde(default, rename = "max-stale")]
+    #[schemars(
+        title = "Cache Max Stale",
+        description = "Maximum age in seconds of cached values used if revalidation fails, unlimited if not set"
+    )]
+    pub max_stale: Option<u64>,
+
+    #[serde(default)]
+    #[schemars(
+        title = "Cache Revalidation",
+        description = "How a stale local secrets cache is revalidated: blocking fetches secrets before running the command, background runs the command with the cached values and refreshes them concurrently"
+    )]
+    pub revalidation: CacheRevalidation,
+
+    #[serde(default, rename = "revalidation-timeout")]
+    #[schemars(
+        title = "Cache Revalidation Timeout",
+        description = "Maximum duration in seconds of a background revalidation"
+    )]
+    pub revalidation_timeout: CacheRevalidationTimeout,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, JsonSchema)]
+pub struct Fetch {
+    #[serde(default, rename = "batch-size")]
+    #[schemars(
+        title = "Fetch Batch Size",
+        description = "Maximum number of secrets requested from Bitwarden at once"
+    )]
+    pub batch_size: FetchBatchSize,
+
+    #[serde(default)]
+    #[schemars(
+        title = "Fetch Concurrency",
+        description = "Maximum number of batches of secrets requested from Bitwarden at the same time"
+    )]
+    pub concurrency: FetchConcurrency,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema, Deref)]
@@ -75,6 +286,37 @@ impl<'a> FromIterator<(String, String)> for Secrets<'a> {
     }
 }
 
+/// Metadata of a secret reported by the provider, dates are in RFC 3339 format
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SecretMetadata {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revised: Option<String>,
+}
+
+/// Metadata of secrets keyed by the key of the secret
+pub type SecretsMetadata = BTreeMap<String, SecretMetadata>;
+
+/// Secrets fetched from a provider, with metadata if the provider reports it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FetchedSecrets<'a> {
+    pub secrets: Secrets<'a>,
+    pub metadata: SecretsMetadata,
+}
+
+impl<'a> From<Secrets<'a>> for FetchedSecrets<'a> {
+    fn from(secrets: Secrets<'a>) -> Self {
+        FetchedSecrets {
+            secrets,
+            metadata: SecretsMetadata::new(),
+        }
+    }
+}
+
 impl<'a> Secrets<'a> {
     pub fn merge(a: &'a Secrets<'a>, b: &'a Secrets<'a>) -> Secrets<'a> {
         Secrets(
@@ -91,14 +333,40 @@ impl<'a> Secrets<'a> {
             .collect()
     }
 
-    pub fn table(&self, reveal: bool) -> String {
-        let mut table = Table::new("{:>} :: {:<}");
+    /// Formats the secrets as a table, adding the last modification date, ID and note of
+    /// secrets that have metadata
+    pub fn table(&self, reveal: bool, metadata: &SecretsMetadata) -> String {
+        let mut table = match metadata.is_empty() {
+            true => Table::new("{:>} :: {:<}"),
+            false => Table::new("{:>} :: {:<}  {:<}  {:<}  {:<}"),
+        };
         for (key, value) in self.iter() {
-            table.add_row(Row::new().with_cell(key).with_cell(if reveal {
+            let mut row = Row::new().with_cell(key).with_cell(if reveal {
                 value.normal()
             } else {
                 "**redacted**".italic().dimmed()
-            }));
+            });
+            if !metadata.is_empty() {
+                // Keys only set by overrides have no metadata
+                let secret_metadata = metadata.get(key.as_ref());
+                let modified = secret_metadata
+                    .and_then(|metadata| metadata.revised.as_ref().or(metadata.created.as_ref()));
+                row = row
+                    .with_cell(modified.map_or("-", String::as_str).dimmed())
+                    .with_cell(
+                        secret_metadata
+                            .map_or("-", |metadata| metadata.id.as_str())
+                            .dimmed(),
+                    )
+                    .with_cell(
+                        secret_metadata
+                            .and_then(|metadata| metadata.note.as_deref())
+                            .filter(|note| !note.is_empty())
+                            .unwrap_or("")
+                            .normal(),
+                    );
+            }
+            table.add_row(row);
         }
         table.to_string()
     }
@@ -124,11 +392,18 @@ pub struct Global<'a> {
 pub struct Profile<'a> {
     #[schemars(
         title = "Profile Bitwarden Project ID",
-        description = "ID of the Bitwarden project"
+        description = "ID of the Bitwarden project, or the reference of the secrets for other providers, e.g. the path of the file relative to the project root"
     )]
     #[serde(rename = "project-id")]
     pub project_id: String,
 
+    #[schemars(
+        title = "Profile Provider",
+        description = "Source of the secrets of this profile: bitwarden fetches them from Bitwarden Secrets Manager, file reads them from an unencrypted dotenv, JSON or YAML file, vault reads them from a passphrase-encrypted vault file"
+    )]
+    #[serde(default)]
+    pub provider: ProviderKind,
+
     #[schemars(
         title = "Profile Overrides",
         description = "Profile-specific secret overrides"
@@ -139,6 +414,54 @@ pub struct Profile<'a> {
         deserialize_with = "deserialize_null_default"
     )]
     pub overrides: Secrets<'a>,
+
+    #[schemars(
+        title = "Profile Protected",
+        description = "Require confirmation before running commands and forbid revealing secrets for this profile"
+    )]
+    #[serde(default)]
+    pub protected: bool,
+
+    #[schemars(
+        title = "Profile Server",
+        description = "Profile-specific Bitwarden server, overrides the global server"
+    )]
+    #[serde(default)]
+    pub server: Server,
+
+    #[schemars(
+        title = "Profile Token Env",
+        description = "Env variable holding the access token of this profile, e.g. for service accounts scoped to one environment"
+    )]
+    #[serde(default, rename = "token-env")]
+    pub token_env: Option<String>,
+
+    #[schemars(
+        title = "Profile Token Command",
+        description = "Command whose output is used as access token of this profile if token-env is not set"
+    )]
+    #[serde(default, rename = "token-command")]
+    pub token_command: Option<String>,
+}
+
+impl<'a> Profile<'a> {
+    /// Profiles naming their own token sources don't fall back to the global ones,
+    /// so a missing token is not replaced by the token of another service account
+    pub fn token_sources<'b>(&'b self, profile_name: &'b str) -> Option<Vec<token::Source<'b>>> {
+        if self.token_env.is_none() && self.token_command.is_none() {
+            return None;
+        }
+
+        Some(
+            self.token_env
+                .as_deref()
+                .map(token::Source::Env)
+                .into_iter()
+                .chain(std::iter::once(token::Source::Login(Some(profile_name))))
+                .chain(self.token_command.as_deref().map(token::Source::Command))
+                .collect(),
+        )
+    }
 }
 
 type ProfilesMap<'a> = HashMap<String, Profile<'a>>;
@@ -170,6 +493,27 @@ pub struct Config<'a> {
     )]
     pub cache: Cache,
 
+    #[schemars(
+        title = "Server",
+        description = "Bitwarden server used by all profiles, defaults to the US cloud"
+    )]
+    #[serde(default)]
+    pub server: Server,
+
+    #[schemars(
+        title = "Fetch",
+        description = "Options related to fetching secrets from Bitwarden, e.g. for projects with many secrets"
+    )]
+    #[serde(default)]
+    pub fetch: Fetch,
+
+    #[schemars(
+        title = "Token Command",
+        description = "Command whose output is used as access token if no token is passed, e.g. `pass show bws/app`, run by the shell in the project root"
+    )]
+    #[serde(default, rename = "token-command")]
+    pub token_command: Option<String>,
+
     #[schemars(
         title = "Global",
         description = "Overrides for global configuration options, applied to all profiles"
@@ -193,6 +537,10 @@ pub struct ConfigEvaluation<'a> {
     pub project_id: &'a str,
     pub max_age: &'a CacheMaxAge,
     pub overrides: Secrets<'a>,
+    pub protected: bool,
+    pub server: Server,
+    pub provider: ProviderKind,
+    pub token_sources: Vec<token::Source<'a>>,
 }
 
 impl<'a> Config<'a> {
@@ -200,6 +548,22 @@ impl<'a> Config<'a> {
         parse_config_file(config_file_path)
     }
 
+    /// Token sources of commands that are not bound to a profile
+    pub fn token_sources(&self) -> Vec<token::Source<'_>> {
+        self.default_token_sources(None)
+    }
+
+    fn default_token_sources<'b>(
+        &'b self,
+        profile_name: Option<&'b str>,
+    ) -> Vec<token::Source<'b>> {
+        std::iter::once(token::Source::Env(token::DEFAULT_ENV))
+            .chain(profile_name.map(|profile_name| token::Source::Login(Some(profile_name))))
+            .chain(std::iter::once(token::Source::Login(None)))
+            .chain(self.token_command.as_deref().map(token::Source::Command))
+            .collect()
+    }
+
     pub fn evaluate<'b>(
         &'b self,
         profile_name: &'b str,
@@ -219,17 +583,25 @@ impl<'a> Config<'a> {
             project_id: &profile.project_id,
             version_req: self.version.clone(),
             max_age: &self.cache.max_age,
+            protected: profile.protected,
+            server: self.server.merge(&profile.server),
+            provider: profile.provider,
+            token_sources: profile
+                .token_sources(profile_name)
+                .unwrap_or_else(|| self.default_token_sources(Some(profile_name))),
         })
     }
 }
 
 fn parse_config_file<'a, P: AsRef<Path>>(file_path: P) -> Result<Config<'a>, anyhow::Error> {
     info!(message = format!("Using configuration file at {:?}", file_path.as_ref()));
-    let mut raw = String::new();
-    let mut file = File::open(file_path)
-        .map_err(|_| ConfigError::Read)
-        .unwrap();
-    let _ = file.read_to_string(&mut raw);
+    let raw = fs::read_to_string(file_path.as_ref()).map_err(|err| {
+        anyhow::anyhow!(
+            "Could not read configuration file {:?}: {}",
+            file_path.as_ref(),
+            err
+        )
+    })?;
 
     Ok(serde_yaml::from_str::<Config>(&raw)
         .map_err(|err| SerdeError::new(raw.to_string(), ErrorTypes::Yaml(err)))?)
@@ -265,6 +637,63 @@ profiles: {{}}
         assert_eq!(config.version, VersionReq::parse("1.0.0").unwrap());
         assert_eq!(config.cache.path.to_str().unwrap(), "/tmp/cache");
         assert_eq!(*config.cache.max_age, 86400);
+        assert_eq!(config.cache.mode, CacheMode::Plain);
+        assert_eq!(config.cache.location, CacheLocation::Project);
+        assert_eq!(config.cache.fallback, CacheFallback::Stale);
+        assert_eq!(config.cache.max_stale, None);
+        assert_eq!(config.cache.revalidation, CacheRevalidation::Blocking);
+        assert_eq!(*config.cache.revalidation_timeout, 30);
+        assert_eq!(config.fetch, Fetch::default());
+    }
+
+    #[test]
+    fn test_parse_fetch() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(
+            temp_file,
+            r#"
+version: "1.0.0"
+cache: {{}}
+fetch:
+  batch-size: 50
+  concurrency: 8
+profiles: {{}}
+"#
+        )
+        .unwrap();
+
+        let config = parse_config_file(temp_file.path()).unwrap();
+        assert_eq!(*config.fetch.batch_size, 50);
+        assert_eq!(*config.fetch.concurrency, 8);
+    }
+
+    #[test]
+    fn test_parse_cache_mode() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(
+            temp_file,
+            r#"
+version: "1.0.0"
+cache:
+  mode: encrypted
+  key-file: ".bwenv-key"
+  location: user
+  revalidation: background
+  revalidation-timeout: 10
+profiles: {{}}
+"#
+        )
+        .unwrap();
+
+        let config = parse_config_file(temp_file.path()).unwrap();
+        assert_eq!(config.cache.mode, CacheMode::Encrypted);
+        assert_eq!(config.cache.location, CacheLocation::User);
+        assert_eq!(config.cache.revalidation, CacheRevalidation::Background);
+        assert_eq!(*config.cache.revalidation_timeout, 10);
+        assert_eq!(
+            config.cache.key_file.as_deref(),
+            Some(Path::new(".bwenv-key"))
+        );
     }
 
     #[test]
@@ -272,6 +701,9 @@ profiles: {{}}
         let config = Config {
             version: VersionReq::parse("1.0.0").unwrap(),
             cache: Cache::default(),
+            server: Server::default(),
+            fetch: Fetch::default(),
+            token_command: None,
             global: None,
             profiles: Profiles::default(),
             path: String::new(),
@@ -317,6 +749,160 @@ profiles:
             eval_result.overrides.get("profile_key").unwrap(),
             "profile_value"
         );
+        assert!(!eval_result.protected);
+        assert_eq!(eval_result.provider, ProviderKind::Bitwarden);
+    }
+
+    #[test]
+    fn test_config_evaluate_protected_profile() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(
+            temp_file,
+            r#"
+version: "1.0.0"
+cache:
+  path: "/tmp/cache"
+global:
+  overrides: {{}}
+profiles:
+  production:
+    project-id: "production_project"
+    protected: true
+"#
+        )
+        .unwrap();
+
+        let config = parse_config_file(temp_file.path()).unwrap();
+        let eval_result = config.evaluate("production").unwrap();
+
+        assert!(eval_result.protected);
+    }
+
+    #[test]
+    fn test_config_evaluate_server() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(
+            temp_file,
+            r#"
+version: "1.0.0"
+cache:
+  path: "/tmp/cache"
+server:
+  region: eu
+global:
+  overrides: {{}}
+profiles:
+  default:
+    project-id: "default_project"
+  self-hosted:
+    project-id: "self_hosted_project"
+    server:
+      identity-url: "https://vault.example.com/identity"
+      api-url: "https://vault.example.com/api"
+"#
+        )
+        .unwrap();
+
+        let config = parse_config_file(temp_file.path()).unwrap();
+
+        let urls = config.evaluate("default").unwrap().server.urls();
+        assert_eq!(urls, ServerRegion::Eu.urls());
+        assert_eq!(urls.api_url, "https://api.bitwarden.eu");
+
+        let urls = config.evaluate("self-hosted").unwrap().server.urls();
+        assert_eq!(urls.identity_url, "https://vault.example.com/identity");
+        assert_eq!(urls.api_url, "https://vault.example.com/api");
+
+        let server = Server {
+            region: Some(ServerRegion::Us),
+            ..Default::default()
+        };
+        let urls = config
+            .evaluate("default")
+            .unwrap()
+            .server
+            .merge(&server)
+            .urls();
+        assert_eq!(urls.identity_url, "https://identity.bitwarden.com");
+
+        let self_hosted = config.evaluate("self-hosted").unwrap().server;
+        let urls = self_hosted.merge(&server).urls();
+        assert_eq!(urls, ServerRegion::Us.urls());
+
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(
+            temp_file,
+            r#"
+version: "1.0.0"
+cache:
+  path: "/tmp/cache"
+server:
+  identity-url: "https://vault.example.com/identity"
+  api-url: "https://vault.example.com/api"
+global:
+  overrides: {{}}
+profiles:
+  self-hosted:
+    project-id: "self_hosted_project"
+  cloud:
+    project-id: "cloud_project"
+    server:
+      region: eu
+"#
+        )
+        .unwrap();
+
+        let config = parse_config_file(temp_file.path()).unwrap();
+
+        let urls = config.evaluate("self-hosted").unwrap().server.urls();
+        assert_eq!(urls.api_url, "https://vault.example.com/api");
+
+        let urls = config.evaluate("cloud").unwrap().server.urls();
+        assert_eq!(urls, ServerRegion::Eu.urls());
+    }
+
+    #[test]
+    fn test_config_evaluate_token_sources() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(
+            temp_file,
+            r#"
+version: "1.0.0"
+cache:
+  path: "/tmp/cache"
+token-command: "pass show bws/default"
+global:
+  overrides: {{}}
+profiles:
+  default:
+    project-id: "default_project"
+  production:
+    project-id: "production_project"
+    token-env: "BWS_TOKEN_PROD"
+    token-command: "pass show bws/production"
+"#
+        )
+        .unwrap();
+
+        let config = parse_config_file(temp_file.path()).unwrap();
+
+        assert_eq!(
+            config.evaluate("default").unwrap().token_sources,
+            vec![
+                token::Source::Env("BWS_ACCESS_TOKEN"),
+                token::Source::Login(Some("default")),
+                token::Source::Login(None),
+                token::Source::Command("pass show bws/default"),
+            ]
+        );
+        assert_eq!(
+            config.evaluate("production").unwrap().token_sources,
+            vec![
+                token::Source::Env("BWS_TOKEN_PROD"),
+                token::Source::Login(Some("production")),
+                token::Source::Command("pass show bws/production"),
+            ]
+        );
     }
 
     #[test]
@@ -324,6 +910,9 @@ profiles:
         let config = Config {
             version: VersionReq::parse("1.0.0").unwrap(),
             cache: Cache::default(),
+            server: Server::default(),
+            fetch: Fetch::default(),
+            token_command: None,
             global: Some(Global {
                 overrides: GlobalOverrides(Secrets(
                     [("global_key".into(), "global_value".into())]
@@ -0,0 +1,247 @@
+use anyhow::anyhow;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tempfile::NamedTempFile;
+
+use crate::crypto::{self, Key};
+use crate::fs::create_private_dir_all;
+use crate::token;
+
+const CREDENTIALS_VERSION: u32 = 1;
+const MACHINE_ID_PATHS: [&str; 2] = ["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+/// Access token stored by `bwenv login`, the token itself is only kept encrypted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Credential {
+    #[serde(rename = "token-id")]
+    pub token_id: Option<String>,
+    pub fingerprint: String,
+    pub created: u64,
+    encrypted: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CredentialsFile {
+    version: u32,
+    #[serde(default)]
+    global: Option<Credential>,
+    #[serde(default)]
+    profiles: BTreeMap<String, Credential>,
+}
+
+impl CredentialsFile {
+    fn get(&self, profile: Option<&str>) -> Option<&Credential> {
+        match profile {
+            Some(profile) => self.profiles.get(profile),
+            None => self.global.as_ref(),
+        }
+    }
+
+    fn insert(&mut self, profile: Option<&str>, credential: Credential) {
+        match profile {
+            Some(profile) => {
+                self.profiles.insert(profile.to_string(), credential);
+            }
+            None => self.global = Some(credential),
+        }
+    }
+
+    fn remove(&mut self, profile: Option<&str>) -> Option<Credential> {
+        match profile {
+            Some(profile) => self.profiles.remove(profile),
+            None => self.global.take(),
+        }
+    }
+}
+
+/// Encrypted store of access tokens in the data directory of the user, keyed by profile.
+///
+/// Tokens are encrypted with a key derived from a random key file next to the store and, on
+/// Linux, the machine ID. This keeps them out of plain text files, but it does not protect them
+/// from anyone able to read the data directory, which holds the key file as well.
+pub struct CredentialStore {
+    directory: PathBuf,
+}
+
+impl CredentialStore {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+
+    /// Store next to the bwenv data file of the user
+    pub fn user() -> Option<Self> {
+        dirs::data_dir().map(|data_dir| Self::new(data_dir.join("bwenv")))
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.directory.join("credentials.yaml")
+    }
+
+    fn key_path(&self) -> PathBuf {
+        self.directory.join("credentials.key")
+    }
+
+    fn read(&self) -> anyhow::Result<CredentialsFile> {
+        let path = self.path();
+        if !path.exists() {
+            return Ok(CredentialsFile {
+                version: CREDENTIALS_VERSION,
+                ..Default::default()
+            });
+        }
+
+        let credentials_file: CredentialsFile =
+            serde_yaml::from_str(&fs::read_to_string(&path)?)
+                .map_err(|err| anyhow!("Invalid credential store {:?}: {}", path, err))?;
+        if credentials_file.version != CREDENTIALS_VERSION {
+            return Err(anyhow!(
+                "Unsupported credential store version {}, upgrade bwenv",
+                credentials_file.version
+            ));
+        }
+        Ok(credentials_file)
+    }
+
+    fn write(&self, credentials_file: &CredentialsFile) -> anyhow::Result<()> {
+        write_private(
+            &self.directory,
+            &self.path(),
+            serde_yaml::to_string(credentials_file)?.as_bytes(),
+        )
+    }
+
+    /// Derives the key of a profile, so entries can't be swapped between profiles
+    fn key(&self, profile: Option<&str>, create: bool) -> anyhow::Result<Key> {
+        let key_path = self.key_path();
+        let local_key = match fs::read(&key_path) {
+            Ok(local_key) => local_key,
+            Err(_) if create => {
+                let local_key = crypto::random_key();
+                write_private(&self.directory, &key_path, &local_key)?;
+                local_key.to_vec()
+            }
+            Err(err) => return Err(anyhow!("Could not read {:?}: {}", key_path, err)),
+        };
+
+        let machine_id = MACHINE_ID_PATHS
+            .iter()
+            .find_map(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        let secret = [local_key.as_slice(), machine_id.trim().as_bytes()].concat();
+        Ok(crypto::derive_key(
+            &secret,
+            &format!("bwenv credentials {}", profile.unwrap_or("")),
+        ))
+    }
+
+    pub fn credential(&self, profile: Option<&str>) -> anyhow::Result<Option<Credential>> {
+        Ok(self.read()?.get(profile).cloned())
+    }
+
+    /// Decrypts the token stored for the profile, or the global one if `profile` is `None`
+    pub fn get(&self, profile: Option<&str>) -> anyhow::Result<Option<String>> {
+        let credential = match self.credential(profile)? {
+            Some(credential) => credential,
+            None => return Ok(None),
+        };
+
+        let key = self.key(profile, false)?;
+        let token = crypto::decrypt(&key, &STANDARD.decode(credential.encrypted)?)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Could not decrypt the stored token, it was stored on another machine, run bwenv login again"
+                )
+            })?;
+        Ok(Some(String::from_utf8(token)?))
+    }
+
+    pub fn set(&self, profile: Option<&str>, token: &str) -> anyhow::Result<Credential> {
+        let mut credentials_file = self.read()?;
+        let key = self.key(profile, true)?;
+        let credential = Credential {
+            token_id: token::access_token_id(token).map(String::from),
+            fingerprint: crypto::fingerprint(token.as_bytes()),
+            created: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .expect("SystemTime before UNIX EPOCH!")
+                .as_millis() as u64,
+            encrypted: STANDARD.encode(crypto::encrypt(&key, token.as_bytes())),
+        };
+
+        credentials_file.insert(profile, credential.clone());
+        self.write(&credentials_file)?;
+        Ok(credential)
+    }
+
+    /// Removes the token of the profile, returning whether one was stored
+    pub fn remove(&self, profile: Option<&str>) -> anyhow::Result<bool> {
+        let mut credentials_file = self.read()?;
+        let removed = credentials_file.remove(profile).is_some();
+
+        if removed {
+            self.write(&credentials_file)?;
+        }
+        Ok(removed)
+    }
+}
+
+fn write_private(directory: &Path, path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    create_private_dir_all(directory)?;
+    // Temporary files are only readable by the user
+    let mut file = NamedTempFile::new_in(directory)?;
+    file.write_all(data)?;
+    file.persist(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const TOKEN: &str = "0.3f1a3c6e-5c4b-4d1e-9d4f-0a1b2c3d4e5f.secret:a2V5";
+
+    #[test]
+    fn stores_tokens_per_profile() {
+        let directory = tempdir().unwrap();
+        let store = CredentialStore::new(directory.path().to_path_buf());
+
+        assert_eq!(store.get(None).unwrap(), None);
+
+        let credential = store.set(Some("production"), TOKEN).unwrap();
+        assert_eq!(
+            credential.token_id.as_deref(),
+            Some("3f1a3c6e-5c4b-4d1e-9d4f-0a1b2c3d4e5f")
+        );
+        assert!(!fs::read_to_string(store.path()).unwrap().contains("secret"));
+
+        assert_eq!(store.get(Some("production")).unwrap().unwrap(), TOKEN);
+        assert_eq!(store.get(Some("staging")).unwrap(), None);
+        assert_eq!(store.get(None).unwrap(), None);
+
+        store.set(None, "0.other.secret:a2V5").unwrap();
+        assert_eq!(store.get(None).unwrap().unwrap(), "0.other.secret:a2V5");
+
+        assert!(store.remove(Some("production")).unwrap());
+        assert!(!store.remove(Some("production")).unwrap());
+        assert_eq!(store.get(Some("production")).unwrap(), None);
+        assert!(store.get(None).unwrap().is_some());
+    }
+
+    #[test]
+    fn tokens_are_bound_to_the_key_file() {
+        let directory = tempdir().unwrap();
+        let store = CredentialStore::new(directory.path().to_path_buf());
+        store.set(None, TOKEN).unwrap();
+
+        fs::write(store.key_path(), crypto::random_key()).unwrap();
+        assert!(store.get(None).is_err());
+    }
+}
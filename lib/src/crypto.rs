@@ -0,0 +1,147 @@
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+const NONCE_LENGTH: usize = 24;
+pub const SALT_LENGTH: usize = 16;
+
+pub type Key = [u8; 32];
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub fn derive_key(secret: &[u8], context: &str) -> Key {
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, secret)
+        .expand(context.as_bytes(), &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+pub fn derive_key_from_passphrase(passphrase: &[u8], salt: &[u8]) -> Option<Key> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase, salt, &mut key)
+        .ok()?;
+    Some(key)
+}
+
+pub fn random_salt() -> [u8; SALT_LENGTH] {
+    let mut salt = [0u8; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+pub fn random_key() -> Key {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+pub fn fingerprint(secret: &[u8]) -> String {
+    Sha256::digest(secret)
+        .iter()
+        .take(8)
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn sign(key: &Key, data: &[u8]) -> Vec<u8> {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub fn verify(key: &Key, data: &[u8], signature: &[u8]) -> bool {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.verify_slice(signature).is_ok()
+}
+
+pub fn encrypt(key: &Key, plaintext: &[u8]) -> Vec<u8> {
+    let cipher = XChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key));
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .expect("Failed to encrypt data");
+
+    let mut data = nonce.to_vec();
+    data.extend(ciphertext);
+    data
+}
+
+pub fn decrypt(key: &Key, data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < NONCE_LENGTH {
+        return None;
+    }
+
+    let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+    let cipher = XChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key));
+    cipher.decrypt(XNonce::from_slice(nonce), ciphertext).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_and_decrypt_roundtrip() {
+        let key = derive_key(b"secret", "test");
+        let data = encrypt(&key, b"plaintext");
+
+        assert_ne!(&data[NONCE_LENGTH..], b"plaintext");
+        assert_eq!(decrypt(&key, &data).unwrap(), b"plaintext");
+    }
+
+    #[test]
+    fn decrypt_with_wrong_key_fails() {
+        let data = encrypt(&derive_key(b"secret", "test"), b"plaintext");
+
+        assert!(decrypt(&derive_key(b"other", "test"), &data).is_none());
+        assert!(decrypt(&derive_key(b"secret", "other"), &data).is_none());
+    }
+
+    #[test]
+    fn fingerprint_is_stable_and_short() {
+        assert_eq!(fingerprint(b"secret"), fingerprint(b"secret"));
+        assert_ne!(fingerprint(b"secret"), fingerprint(b"other"));
+        assert_eq!(fingerprint(b"secret").len(), 16);
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let key = derive_key(b"secret", "test");
+        let signature = sign(&key, b"data");
+
+        assert!(verify(&key, b"data", &signature));
+        assert!(!verify(&key, b"tampered", &signature));
+        assert!(!verify(&derive_key(b"other", "test"), b"data", &signature));
+    }
+
+    #[test]
+    fn passphrase_keys_depend_on_salt() {
+        let salt = random_salt();
+        let key = derive_key_from_passphrase(b"passphrase", &salt).unwrap();
+
+        assert_eq!(
+            derive_key_from_passphrase(b"passphrase", &salt).unwrap(),
+            key
+        );
+        assert_ne!(
+            derive_key_from_passphrase(b"passphrase", &random_salt()).unwrap(),
+            key
+        );
+        assert!(derive_key_from_passphrase(b"passphrase", b"short").is_none());
+    }
+
+    #[test]
+    fn decrypt_truncated_data_fails() {
+        let key = derive_key(b"secret", "test");
+
+        assert!(decrypt(&key, b"short").is_none());
+    }
+}
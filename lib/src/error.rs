@@ -3,4 +3,173 @@ pub enum ConfigError {
     Read,
     NotFound,
     NoProfile,
+    UnknownFormat,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BitwardenError {
+    InvalidToken,
+    ExpiredToken,
+    ProjectNotFound(String),
+    RateLimited,
+    Network(String),
+    InvalidProjectId(String),
+    Other(String),
+}
+
+impl BitwardenError {
+    pub fn from_status(status: u16, message: String, project_id: Option<&str>) -> Self {
+        match (status, project_id) {
+            (401, _) | (403 | 404, None) => BitwardenError::ExpiredToken,
+            (403 | 404, Some(project_id)) => {
+                BitwardenError::ProjectNotFound(project_id.to_string())
+            }
+            (429, _) => BitwardenError::RateLimited,
+            (500.., _) => BitwardenError::Network(format!("server responded with {}", status)),
+            _ => BitwardenError::Other(format!("[{}] {}", status, message)),
+        }
+    }
+
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            BitwardenError::RateLimited | BitwardenError::Network(_)
+        )
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            BitwardenError::InvalidToken => 10,
+            BitwardenError::ExpiredToken => 11,
+            BitwardenError::ProjectNotFound(_) => 12,
+            BitwardenError::RateLimited => 13,
+            BitwardenError::Network(_) => 14,
+            BitwardenError::InvalidProjectId(_) => 15,
+            BitwardenError::Other(_) => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for BitwardenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitwardenError::InvalidToken => write!(
+                f,
+                "the access token is malformed, check the token passed with --token, --token-file, --token-stdin or BWS_ACCESS_TOKEN, stored with bwenv login, or read with token-env or token-command of the profile"
+            ),
+            BitwardenError::ExpiredToken => write!(
+                f,
+                "the access token was rejected, it may have expired or been revoked, create a new one for the service account"
+            ),
+            BitwardenError::ProjectNotFound(project_id) => write!(
+                f,
+                "project {:?} does not exist or the service account has no access to it",
+                project_id
+            ),
+            BitwardenError::RateLimited => {
+                write!(f, "Bitwarden is rate limiting requests, try again later")
+            }
+            BitwardenError::Network(message) => {
+                write!(f, "could not reach Bitwarden: {}", message)
+            }
+            BitwardenError::InvalidProjectId(project_id) => write!(
+                f,
+                "project ID {:?} is not a valid UUID, check project-id in the configuration file",
+                project_id
+            ),
+            BitwardenError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for BitwardenError {}
+
+#[derive(Debug, PartialEq)]
+pub enum ProviderError {
+    Bitwarden(BitwardenError),
+    File(String),
+    Vault(String),
+    Unsupported(&'static str),
+    SecretNotFound(String),
+    SecretExists(String),
+}
+
+impl ProviderError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ProviderError::Bitwarden(err) => err.exit_code(),
+            ProviderError::SecretNotFound(_) => 16,
+            ProviderError::File(_)
+            | ProviderError::Vault(_)
+            | ProviderError::Unsupported(_)
+            | ProviderError::SecretExists(_) => 1,
+        }
+    }
+}
+
+impl From<BitwardenError> for ProviderError {
+    fn from(err: BitwardenError) -> Self {
+        ProviderError::Bitwarden(err)
+    }
+}
+
+impl std::fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProviderError::Bitwarden(err) => write!(f, "{}", err),
+            ProviderError::File(message) | ProviderError::Vault(message) => {
+                write!(f, "{}", message)
+            }
+            ProviderError::Unsupported(operation) => {
+                write!(
+                    f,
+                    "the provider of this profile does not support {}",
+                    operation
+                )
+            }
+            ProviderError::SecretNotFound(key) => {
+                write!(f, "the secret {:?} does not exist in the project", key)
+            }
+            ProviderError::SecretExists(key) => {
+                write!(f, "the secret {:?} already exists in the project", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_status_codes() {
+        assert_eq!(
+            BitwardenError::from_status(401, String::new(), Some("project")),
+            BitwardenError::ExpiredToken
+        );
+        assert_eq!(
+            BitwardenError::from_status(404, String::new(), Some("project")),
+            BitwardenError::ProjectNotFound(String::from("project"))
+        );
+        assert_eq!(
+            BitwardenError::from_status(429, String::new(), Some("project")),
+            BitwardenError::RateLimited
+        );
+        assert_eq!(
+            BitwardenError::from_status(404, String::new(), None),
+            BitwardenError::ExpiredToken
+        );
+        assert!(BitwardenError::from_status(503, String::new(), Some("project")).is_transient());
+        assert_eq!(
+            BitwardenError::from_status(400, String::from("bad request"), Some("project"))
+                .exit_code(),
+            1
+        );
+        assert_eq!(
+            ProviderError::SecretNotFound(String::from("KEY")).exit_code(),
+            16
+        );
+    }
 }
@@ -1,4 +1,10 @@
-use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::{
+    fs::DirBuilder,
+    io,
+    path::{Path, PathBuf},
+};
 
 pub fn find_up(filename: &str, max_parents: Option<i32>, cwd: Option<&Path>) -> Option<PathBuf> {
     let mut current_directory = cwd?;
@@ -19,9 +25,22 @@ pub fn find_up(filename: &str, max_parents: Option<i32>, cwd: Option<&Path>) ->
     None
 }
 
+/// Creates a directory and its missing parents only accessible by the owner. The directory
+/// itself is restricted even if it already existed, existing parents keep their mode.
+pub fn create_private_dir_all(path: &Path) -> io::Result<()> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder.create(path)?;
+    #[cfg(unix)]
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o700))?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use super::find_up;
+    use super::{create_private_dir_all, find_up};
     use std::fs::{self, File};
     use std::io::Write;
     use std::path::Path;
@@ -82,4 +101,26 @@ mod tests {
 
         std::env::set_current_dir(Path::new("/")).unwrap();
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_dir_is_only_accessible_by_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempdir().unwrap();
+        let private_dir = temp_dir.path().join("level1").join("level2");
+
+        create_private_dir_all(&private_dir).unwrap();
+
+        let mode = fs::metadata(&private_dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        let existing_dir = temp_dir.path().join("existing");
+        fs::create_dir(&existing_dir).unwrap();
+        fs::set_permissions(&existing_dir, fs::Permissions::from_mode(0o755)).unwrap();
+        create_private_dir_all(&existing_dir).unwrap();
+
+        let mode = fs::metadata(&existing_dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
 }
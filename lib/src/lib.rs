@@ -1,11 +1,18 @@
 pub mod bitwarden;
 pub mod cache;
 pub mod config;
+pub mod config_json;
 pub mod config_toml;
 pub mod config_yaml;
+pub mod credentials;
+pub mod crypto;
 pub mod data;
 pub mod error;
 pub mod fs;
+pub mod provider;
 pub mod schema_types;
+pub mod secrets_file;
 pub mod time;
+pub mod token;
+pub mod vault;
 pub mod version;
@@ -0,0 +1,132 @@
+use async_trait::async_trait;
+use std::path::PathBuf;
+
+use crate::bitwarden::BitwardenClient;
+use crate::config_yaml::{Fetch, FetchedSecrets, ProviderKind, Secrets, ServerUrls};
+use crate::crypto;
+use crate::error::ProviderError;
+use crate::fs::create_private_dir_all;
+use crate::secrets_file;
+use crate::vault::VaultProvider;
+
+/// Source of the secrets of a profile, selected by the `provider` of the profile
+#[async_trait(?Send)]
+pub trait SecretsProvider {
+    /// Fetches all secrets of the project identified by `reference`
+    async fn fetch<'a>(&self, reference: &str) -> Result<Secrets<'a>, ProviderError>;
+
+    /// Fetches all secrets with their metadata, providers without metadata keep the default
+    async fn fetch_with_metadata<'a>(
+        &self,
+        reference: &str,
+    ) -> Result<FetchedSecrets<'a>, ProviderError> {
+        Ok(self.fetch(reference).await?.into())
+    }
+
+    /// Creates or updates a secret, providers that are read-only keep the default
+    async fn set(&self, _reference: &str, _key: &str, _value: &str) -> Result<(), ProviderError> {
+        Err(ProviderError::Unsupported("writing secrets"))
+    }
+
+    /// Deletes a secret, providers that are read-only keep the default
+    async fn delete(&self, _reference: &str, _key: &str) -> Result<(), ProviderError> {
+        Err(ProviderError::Unsupported("deleting secrets"))
+    }
+
+    /// Renames a secret keeping its value, providers that are read-only keep the default
+    async fn rename(&self, _reference: &str, _from: &str, _to: &str) -> Result<(), ProviderError> {
+        Err(ProviderError::Unsupported("renaming secrets"))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProviderContext {
+    pub token: String,
+    pub server_urls: ServerUrls,
+    pub root_dir: PathBuf,
+    pub vault_passphrase: Option<String>,
+    /// Directory persisting the Bitwarden sessions, one file per access token
+    pub auth_state_dir: Option<PathBuf>,
+    pub fetch: Fetch,
+}
+
+/// Creates the provider of the given kind, logging in if the provider requires it
+pub async fn connect(
+    kind: &ProviderKind,
+    context: &ProviderContext,
+) -> Result<Box<dyn SecretsProvider>, ProviderError> {
+    match kind {
+        ProviderKind::Bitwarden => {
+            let state_file = context
+                .auth_state_dir
+                .as_ref()
+                .filter(|auth_state_dir| create_private_dir_all(auth_state_dir).is_ok())
+                .map(|auth_state_dir| {
+                    auth_state_dir.join(crypto::fingerprint(context.token.as_bytes()))
+                });
+            Ok(Box::new(
+                BitwardenClient::new(
+                    context.token.clone(),
+                    &context.server_urls,
+                    state_file,
+                    context.fetch,
+                )
+                .await?,
+            ))
+        }
+        ProviderKind::File => Ok(Box::new(FileProvider {
+            root_dir: context.root_dir.clone(),
+        })),
+        ProviderKind::Vault => Ok(Box::new(VaultProvider {
+            root_dir: context.root_dir.clone(),
+            passphrase: context.vault_passphrase.clone(),
+        })),
+    }
+}
+
+/// Reads secrets from an unencrypted dotenv, JSON or YAML file relative to the project root,
+/// e.g. for local development and tests
+pub struct FileProvider {
+    root_dir: PathBuf,
+}
+
+#[async_trait(?Send)]
+impl SecretsProvider for FileProvider {
+    async fn fetch<'a>(&self, reference: &str) -> Result<Secrets<'a>, ProviderError> {
+        secrets_file::read(&self.root_dir.join(reference))
+            .map_err(|err| ProviderError::File(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn file_provider_reads_relative_to_root() {
+        let root_dir = tempdir().unwrap();
+        std::fs::write(root_dir.path().join("secrets.env"), "KEY=value\n").unwrap();
+        let context = ProviderContext {
+            token: String::new(),
+            server_urls: crate::config_yaml::ServerRegion::Us.urls(),
+            root_dir: root_dir.path().to_path_buf(),
+            vault_passphrase: None,
+            auth_state_dir: None,
+            fetch: Fetch::default(),
+        };
+
+        let provider = connect(&ProviderKind::File, &context).await.unwrap();
+        let secrets = provider.fetch("secrets.env").await.unwrap();
+        assert_eq!(secrets.get("KEY").unwrap(), "value");
+
+        assert!(matches!(
+            provider.fetch("missing.env").await,
+            Err(ProviderError::File(_))
+        ));
+        assert_eq!(
+            provider.set("secrets.env", "KEY", "other").await,
+            Err(ProviderError::Unsupported("writing secrets"))
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::anyhow;
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::config_yaml::Secrets;
+
+#[derive(Debug, PartialEq)]
+pub enum Format {
+    Dotenv,
+    Json,
+    Yaml,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(std::ffi::OsStr::to_str) {
+            Some("json") => Format::Json,
+            Some("yaml") | Some("yml") => Format::Yaml,
+            _ => Format::Dotenv,
+        }
+    }
+}
+
+pub fn read<'a>(path: &Path) -> anyhow::Result<Secrets<'a>> {
+    let raw = fs::read_to_string(path)
+        .map_err(|err| anyhow!("Could not read secrets file {:?}: {}", path, err))?;
+    parse(&raw, Format::from_path(path))
+}
+
+pub fn parse<'a>(raw: &str, format: Format) -> anyhow::Result<Secrets<'a>> {
+    match format {
+        Format::Dotenv => Ok(dotenv_parser::parse_dotenv(raw)
+            .map_err(|err| anyhow!("Invalid dotenv file: {}", err))?
+            .into_iter()
+            .collect()),
+        Format::Json => Ok(
+            serde_json::from_str::<HashMap<String, serde_json::Value>>(raw)?
+                .into_iter()
+                .map(|(key, value)| match value {
+                    serde_json::Value::String(value) => (key, value),
+                    value => (key, value.to_string()),
+                })
+                .collect(),
+        ),
+        Format::Yaml => Ok(serde_yaml::from_str::<Option<Secrets>>(raw)?.unwrap_or_default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_format_from_extension() {
+        assert_eq!(Format::from_path(Path::new("secrets.json")), Format::Json);
+        assert_eq!(Format::from_path(Path::new("secrets.yml")), Format::Yaml);
+        assert_eq!(Format::from_path(Path::new(".env.local")), Format::Dotenv);
+    }
+
+    #[test]
+    fn parses_all_formats() {
+        let dotenv = parse("KEY=value\nOTHER_KEY=\"other value\"\n", Format::Dotenv).unwrap();
+        let json = parse(
+            r#"{"KEY": "value", "OTHER_KEY": "other value"}"#,
+            Format::Json,
+        )
+        .unwrap();
+        let yaml = parse("KEY: value\nOTHER_KEY: other value\n", Format::Yaml).unwrap();
+
+        assert_eq!(dotenv, json);
+        assert_eq!(json, yaml);
+        assert_eq!(yaml.get("OTHER_KEY").unwrap(), "other value");
+    }
+
+    #[test]
+    fn stringifies_json_values() {
+        let secrets = parse(r#"{"FORCE_COLOR": 1, "DEBUG": true}"#, Format::Json).unwrap();
+
+        assert_eq!(secrets.get("FORCE_COLOR").unwrap(), "1");
+        assert_eq!(secrets.get("DEBUG").unwrap(), "true");
+    }
+}
@@ -12,6 +12,27 @@ pub fn is_date_older_than_n_seconds(unix_millis: u64, n_seconds: &u64) -> bool {
     date_seconds < threshold_time
 }
 
+pub fn seconds_since(unix_millis: u64) -> u64 {
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("SystemTime before UNIX EPOCH!");
+
+    current_time.as_secs().saturating_sub(unix_millis / 1000)
+}
+
+pub fn format_duration(seconds: u64) -> String {
+    let days = seconds / 86400;
+    let hours = seconds % 86400 / 3600;
+    let minutes = seconds % 3600 / 60;
+
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", seconds),
+        (0, 0, _) => format!("{}m {}s", minutes, seconds % 60),
+        (0, _, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h", days, hours),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -61,4 +82,24 @@ mod tests {
             &10
         ));
     }
+
+    #[test]
+    fn seconds_since_past_date() {
+        let one_minute_ago = SystemTime::now()
+            .checked_sub(Duration::from_secs(60))
+            .expect("Failed to calculate time")
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis() as u64;
+
+        assert!((60..=61).contains(&seconds_since(one_minute_ago)));
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(42), "42s");
+        assert_eq!(format_duration(125), "2m 5s");
+        assert_eq!(format_duration(7320), "2h 2m");
+        assert_eq!(format_duration(273600), "3d 4h");
+    }
 }
@@ -0,0 +1,236 @@
+use anyhow::anyhow;
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+    process::{Command, Stdio},
+};
+use tracing::warn;
+
+use crate::credentials::CredentialStore;
+
+/// Env variable holding the access token unless a profile names its own sources
+pub const DEFAULT_ENV: &str = "BWS_ACCESS_TOKEN";
+
+/// Source of the access token configured in the configuration file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source<'a> {
+    Env(&'a str),
+    Command(&'a str),
+    /// Token stored by `bwenv login` for the profile, or for all profiles if `None`
+    Login(Option<&'a str>),
+}
+
+impl std::fmt::Display for Source<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Env(name) => write!(f, "env variable {}", name),
+            Source::Command(command) => write!(f, "token command {:?}", command),
+            Source::Login(Some(profile)) => write!(f, "login of profile {:?}", profile),
+            Source::Login(None) => write!(f, "login"),
+        }
+    }
+}
+
+/// ID of a service account access token of the form `0.<id>.<secret>:<key>`,
+/// which unlike the token itself can be shown
+pub fn access_token_id(token: &str) -> Option<&str> {
+    match token.split('.').collect::<Vec<_>>()[..] {
+        ["0", id, _] if !id.is_empty() => Some(id),
+        _ => None,
+    }
+}
+
+/// Removes surrounding whitespace, e.g. the trailing newline of a file or command output,
+/// failing if nothing is left
+fn normalize(token: &str, source: &str) -> anyhow::Result<String> {
+    match token.trim() {
+        "" => Err(anyhow!("The access token read from {} is empty", source)),
+        token => Ok(token.to_string()),
+    }
+}
+
+pub fn read_file(path: &Path) -> anyhow::Result<String> {
+    let token = fs::read_to_string(path)
+        .map_err(|err| anyhow!("Could not read token file {:?}: {}", path, err))?;
+    normalize(&token, &format!("{:?}", path))
+}
+
+/// Reads the first line of stdin, the remaining input is left to the wrapped command. Stdin
+/// is read one byte at a time through a duplicate of its descriptor, as the buffer of
+/// `std::io::Stdin` would consume input beyond the line
+pub fn read_stdin() -> anyhow::Result<String> {
+    let read_error = |err: io::Error| anyhow!("Could not read token from stdin: {}", err);
+
+    #[cfg(unix)]
+    let stdin = std::os::fd::AsFd::as_fd(&io::stdin()).try_clone_to_owned();
+    #[cfg(windows)]
+    let stdin = std::os::windows::io::AsHandle::as_handle(&io::stdin()).try_clone_to_owned();
+    let mut stdin = fs::File::from(stdin.map_err(read_error)?);
+
+    let mut token = Vec::new();
+    let mut byte = [0; 1];
+    loop {
+        match stdin.read(&mut byte) {
+            Ok(0) => break,
+            Ok(_) if byte[0] == b'\n' => break,
+            Ok(_) => token.push(byte[0]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(read_error(err)),
+        }
+    }
+    let token = String::from_utf8(token)
+        .map_err(|_| anyhow!("Could not read token from stdin: it is not valid UTF-8"))?;
+    normalize(&token, "stdin")
+}
+
+/// Runs a token helper using the shell and reads the token from its stdout,
+/// stderr and stdin are inherited so the helper can prompt, e.g. for a GPG passphrase
+pub fn run_command(command: &str, dir: &Path) -> anyhow::Result<String> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+
+    let output = Command::new(shell)
+        .arg(flag)
+        .arg(command)
+        .current_dir(dir)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|err| anyhow!("Could not run token command {:?}: {}", command, err))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Token command {:?} failed with {}",
+            command,
+            output.status
+        ));
+    }
+
+    normalize(
+        &String::from_utf8(output.stdout)?,
+        &format!("token command {:?}", command),
+    )
+}
+
+/// Reads the token from the first source that provides one, env variables that are not set
+/// and missing logins are skipped while failing commands are errors
+pub fn resolve<'a>(
+    sources: &[Source<'a>],
+    dir: &Path,
+) -> anyhow::Result<Option<(String, Source<'a>)>> {
+    resolve_with(sources, dir, |name| std::env::var(name).ok())
+}
+
+/// Like `resolve`, reading env variables with `env`
+fn resolve_with<'a>(
+    sources: &[Source<'a>],
+    dir: &Path,
+    env: impl Fn(&str) -> Option<String>,
+) -> anyhow::Result<Option<(String, Source<'a>)>> {
+    for source in sources {
+        match source {
+            Source::Env(name) => match env(name) {
+                Some(token) if !token.trim().is_empty() => {
+                    return Ok(Some((token.trim().to_string(), *source)))
+                }
+                _ => continue,
+            },
+            Source::Command(command) => {
+                return run_command(command, dir).map(|token| Some((token, *source)))
+            }
+            Source::Login(profile) => {
+                let stored = CredentialStore::user().map(|store| store.get(*profile));
+                match stored {
+                    Some(Ok(Some(token))) => return Ok(Some((token, *source))),
+                    Some(Err(err)) => {
+                        warn!(message = format!("Ignoring the {}: {}", source, err));
+                        continue;
+                    }
+                    _ => continue,
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn parses_access_token_id() {
+        assert_eq!(
+            access_token_id("0.3f1a3c6e-5c4b-4d1e-9d4f-0a1b2c3d4e5f.secret:a2V5"),
+            Some("3f1a3c6e-5c4b-4d1e-9d4f-0a1b2c3d4e5f")
+        );
+        assert_eq!(access_token_id("not a token"), None);
+        assert_eq!(access_token_id("1.id.secret:key"), None);
+    }
+
+    #[test]
+    fn reads_trimmed_token_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("token");
+        fs::write(&path, "0.token\n").unwrap();
+        assert_eq!(read_file(&path).unwrap(), "0.token");
+
+        fs::write(&path, "\n").unwrap();
+        assert!(read_file(&path).is_err());
+        assert!(read_file(&dir.path().join("missing")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reads_token_from_command() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("token"), "0.token\n").unwrap();
+
+        assert_eq!(run_command("cat token", dir.path()).unwrap(), "0.token");
+        assert!(run_command("exit 1", dir.path()).is_err());
+        assert!(run_command("true", dir.path()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_first_available_source() {
+        let dir = tempdir().unwrap();
+        let env = |name: &str| (name == "BWENV_TEST_TOKEN").then(|| String::from("0.env"));
+
+        assert_eq!(
+            resolve_with(
+                &[
+                    Source::Env("BWENV_TEST_MISSING_TOKEN"),
+                    Source::Env("BWENV_TEST_TOKEN"),
+                    Source::Command("echo 0.command"),
+                ],
+                dir.path(),
+                env
+            )
+            .unwrap()
+            .map(|(token, _)| token),
+            Some(String::from("0.env"))
+        );
+        assert_eq!(
+            resolve_with(
+                &[
+                    Source::Env("BWENV_TEST_MISSING_TOKEN"),
+                    Source::Command("echo 0.command"),
+                ],
+                dir.path(),
+                env
+            )
+            .unwrap(),
+            Some((String::from("0.command"), Source::Command("echo 0.command")))
+        );
+        assert!(resolve_with(&[Source::Command("exit 1")], dir.path(), env).is_err());
+        assert_eq!(
+            resolve_with(&[Source::Env("BWENV_TEST_MISSING_TOKEN")], dir.path(), env).unwrap(),
+            None
+        );
+    }
+}
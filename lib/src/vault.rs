@@ -0,0 +1,214 @@
+use anyhow::anyhow;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use tempfile::NamedTempFile;
+
+use crate::config_yaml::Secrets;
+use crate::crypto;
+use crate::error::ProviderError;
+use crate::provider::SecretsProvider;
+use crate::secrets_file::{self, Format};
+
+const VAULT_VERSION: u32 = 1;
+
+/// Passphrase-encrypted file holding the secrets of a profile as YAML,
+/// the key is derived from the passphrase using Argon2id
+#[derive(Debug, Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    salt: String,
+    encrypted: String,
+}
+
+/// Encrypts YAML secrets, failing if they can not be parsed
+pub fn seal(plaintext: &str, passphrase: &str) -> anyhow::Result<String> {
+    secrets_file::parse(plaintext, Format::Yaml)?;
+
+    let salt = crypto::random_salt();
+    let key = crypto::derive_key_from_passphrase(passphrase.as_bytes(), &salt)
+        .ok_or_else(|| anyhow!("Could not derive vault key"))?;
+    let vault_file = VaultFile {
+        version: VAULT_VERSION,
+        salt: STANDARD.encode(salt),
+        encrypted: STANDARD.encode(crypto::encrypt(&key, plaintext.as_bytes())),
+    };
+    Ok(serde_yaml::to_string(&vault_file)?)
+}
+
+/// Decrypts a vault, returning the YAML secrets
+pub fn open(vault: &str, passphrase: &str) -> anyhow::Result<String> {
+    let vault_file: VaultFile =
+        serde_yaml::from_str(vault).map_err(|err| anyhow!("Invalid vault file: {}", err))?;
+    if vault_file.version != VAULT_VERSION {
+        return Err(anyhow!(
+            "Unsupported vault version {}, upgrade bwenv",
+            vault_file.version
+        ));
+    }
+
+    let salt = STANDARD.decode(vault_file.salt)?;
+    let encrypted = STANDARD.decode(vault_file.encrypted)?;
+    let key = crypto::derive_key_from_passphrase(passphrase.as_bytes(), &salt)
+        .ok_or_else(|| anyhow!("Could not derive vault key"))?;
+    let plaintext = crypto::decrypt(&key, &encrypted)
+        .ok_or_else(|| anyhow!("Could not decrypt vault, the passphrase is wrong"))?;
+    Ok(String::from_utf8(plaintext)?)
+}
+
+/// Secret keying the cache of a vault profile, derived from the Argon2id key of the vault so
+/// caches never hold a fast hash of the passphrase. Sealing the vault again changes its salt
+/// and therefore the secret, so edited vaults are revalidated
+pub fn cache_secret(path: &Path, passphrase: &str) -> anyhow::Result<String> {
+    let vault = fs::read_to_string(path)
+        .map_err(|err| anyhow!("Could not read vault {:?}: {}", path, err))?;
+    let vault_file: VaultFile =
+        serde_yaml::from_str(&vault).map_err(|err| anyhow!("Invalid vault file: {}", err))?;
+    let salt = STANDARD.decode(vault_file.salt)?;
+    let key = crypto::derive_key_from_passphrase(passphrase.as_bytes(), &salt)
+        .ok_or_else(|| anyhow!("Could not derive vault key"))?;
+    Ok(STANDARD.encode(crypto::derive_key(&key, "bwenv vault cache")))
+}
+
+/// Writes a sealed vault through a temporary file next to it, so an interrupted write leaves
+/// the previous vault intact
+pub fn write(path: &Path, vault: &str) -> io::Result<()> {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut file = NamedTempFile::new_in(directory)?;
+    file.write_all(vault.as_bytes())?;
+    file.persist(path)?;
+    Ok(())
+}
+
+pub fn read<'a>(path: &Path, passphrase: &str) -> anyhow::Result<Secrets<'a>> {
+    let vault = fs::read_to_string(path)
+        .map_err(|err| anyhow!("Could not read vault {:?}: {}", path, err))?;
+    secrets_file::parse(&open(&vault, passphrase)?, Format::Yaml)
+}
+
+/// Serializes secrets as YAML sorted by key, e.g. to encrypt them into a vault
+pub fn to_plaintext(secrets: &Secrets) -> String {
+    let secrets: BTreeMap<&str, &str> = secrets
+        .iter()
+        .map(|(key, value)| (key.as_ref(), value.as_ref()))
+        .collect();
+    match secrets.is_empty() {
+        true => String::new(),
+        false => serde_yaml::to_string(&secrets).unwrap(),
+    }
+}
+
+/// Reads secrets from a vault relative to the project root
+pub struct VaultProvider {
+    pub root_dir: PathBuf,
+    pub passphrase: Option<String>,
+}
+
+#[async_trait(?Send)]
+impl SecretsProvider for VaultProvider {
+    async fn fetch<'a>(&self, reference: &str) -> Result<Secrets<'a>, ProviderError> {
+        let passphrase = self.passphrase.as_ref().ok_or_else(|| {
+            ProviderError::Vault(String::from(
+                "no vault passphrase, pass --vault-passphrase or set BWENV_VAULT_PASSPHRASE",
+            ))
+        })?;
+        read(&self.root_dir.join(reference), passphrase)
+            .map_err(|err| ProviderError::Vault(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn seal_and_open_roundtrip() {
+        let vault = seal("KEY: value\n", "passphrase").unwrap();
+        assert!(!vault.contains("value"));
+
+        assert_eq!(open(&vault, "passphrase").unwrap(), "KEY: value\n");
+        assert!(open(&vault, "wrong").is_err());
+    }
+
+    #[test]
+    fn seal_rejects_invalid_secrets() {
+        assert!(seal("- not\n- a map\n", "passphrase").is_err());
+        assert!(seal("# no secrets yet\n", "passphrase").is_ok());
+    }
+
+    #[test]
+    fn cache_secret_is_derived_from_vault_key() {
+        let root_dir = tempdir().unwrap();
+        let path = root_dir.path().join("secrets.vault");
+        fs::write(&path, seal("KEY: value\n", "passphrase").unwrap()).unwrap();
+
+        let secret = cache_secret(&path, "passphrase").unwrap();
+        assert_eq!(cache_secret(&path, "passphrase").unwrap(), secret);
+        assert_ne!(secret, "passphrase");
+        assert_ne!(cache_secret(&path, "other").unwrap(), secret);
+
+        fs::write(&path, seal("KEY: value\n", "passphrase").unwrap()).unwrap();
+        assert_ne!(cache_secret(&path, "passphrase").unwrap(), secret);
+        assert!(cache_secret(&root_dir.path().join("missing.vault"), "passphrase").is_err());
+    }
+
+    #[test]
+    fn write_replaces_vault() {
+        let root_dir = tempdir().unwrap();
+        let path = root_dir.path().join("secrets.vault");
+        fs::write(&path, "previous").unwrap();
+
+        write(&path, "sealed").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "sealed");
+        assert_eq!(fs::read_dir(root_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn plaintext_is_sorted() {
+        let secrets = Secrets(
+            [("B".into(), "2".into()), ("A".into(), "1".into())]
+                .iter()
+                .cloned()
+                .collect(),
+        );
+
+        assert!(to_plaintext(&secrets).find("A:") < to_plaintext(&secrets).find("B:"));
+        assert_eq!(to_plaintext(&Secrets::default()), "");
+    }
+
+    #[tokio::test]
+    async fn vault_provider_requires_passphrase() {
+        let root_dir = tempdir().unwrap();
+        fs::write(
+            root_dir.path().join("secrets.vault"),
+            seal("KEY: value\n", "passphrase").unwrap(),
+        )
+        .unwrap();
+
+        let provider = VaultProvider {
+            root_dir: root_dir.path().to_path_buf(),
+            passphrase: Some(String::from("passphrase")),
+        };
+        let secrets = provider.fetch("secrets.vault").await.unwrap();
+        assert_eq!(secrets.get("KEY").unwrap(), "value");
+
+        let provider = VaultProvider {
+            root_dir: root_dir.path().to_path_buf(),
+            passphrase: None,
+        };
+        assert!(matches!(
+            provider.fetch("secrets.vault").await,
+            Err(ProviderError::Vault(_))
+        ));
+    }
+}
@@ -2,10 +2,17 @@ use once_cell::sync::Lazy;
 use reqwest::Client;
 use semver::Version;
 use serde::Deserialize;
+use std::time::Duration;
 
 static GITHUB_API_URL: &str = "https://api.github.com/repos/titanom/bwenv/releases/latest";
 
-static CLIENT: Lazy<Client> = Lazy::new(Client::new);
+// Bounded so an unreachable network does not delay the command
+static CLIENT: Lazy<Client> = Lazy::new(|| {
+    Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .unwrap_or_default()
+});
 
 #[derive(Deserialize)]
 struct GithubRelease {
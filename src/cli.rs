@@ -1,4 +1,6 @@
+use bwenv_lib::config_yaml;
 use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -12,13 +14,39 @@ pub struct Cli {
     #[arg(
         short,
         long,
-        long_help = "Access token for the service account",
+        long_help = "Access token for the service account, falls back to the env variable BWS_ACCESS_TOKEN and the token-command of the configuration file",
         help = "Access token for the service account",
-        env = "BWS_ACCESS_TOKEN",
         required = false,
-        hide_env_values = true
+        conflicts_with_all = ["token_file", "token_stdin"]
+    )]
+    pub token: Option<String>,
+
+    #[arg(
+        long,
+        long_help = "Read the access token from a file, surrounding whitespace is removed",
+        help = "Read the access token from a file",
+        required = false,
+        conflicts_with = "token_stdin"
+    )]
+    pub token_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        long_help = "Read the access token from the first line of stdin, the remaining input is passed to the command",
+        help = "Read the access token from stdin",
+        required = false
     )]
-    pub token: String,
+    pub token_stdin: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        long_help = "Pass the access token to the command as BWS_ACCESS_TOKEN, by default it is removed from the environment of the command",
+        help = "Pass the access token to the command",
+        required = false
+    )]
+    pub pass_token: bool,
 
     #[arg(
         short,
@@ -30,6 +58,16 @@ pub struct Cli {
     )]
     pub profile: Option<String>,
 
+    #[arg(
+        short,
+        long,
+        long_help = "Path to the configuration file, skips searching for bwenv.yaml, bwenv.json or bwenv.toml",
+        help = "Path to the configuration file",
+        env = "BWENV_CONFIG",
+        required = false
+    )]
+    pub config: Option<PathBuf>,
+
     #[arg(
         short,
         long,
@@ -40,6 +78,79 @@ pub struct Cli {
         required = false
     )]
     pub log_level: LogLevel,
+
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        help = "Skip the confirmation prompt of protected profiles and secret changes",
+        long_help = "Skip the confirmation prompt of protected profiles and secret changes, required to run protected profiles or change secrets in non-interactive contexts",
+        required = false
+    )]
+    pub yes: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Region of the Bitwarden cloud",
+        long_help = "Region of the Bitwarden cloud, overrides the server configured in the configuration file",
+        env = "BWENV_SERVER_REGION",
+        required = false
+    )]
+    pub region: Option<Region>,
+
+    #[arg(
+        long,
+        help = "URL of the Bitwarden identity service",
+        long_help = "URL of the Bitwarden identity service, e.g. of a self-hosted server, takes precedence over the region",
+        env = "BWENV_IDENTITY_URL",
+        required = false
+    )]
+    pub identity_url: Option<String>,
+
+    #[arg(
+        long,
+        help = "URL of the Bitwarden API",
+        long_help = "URL of the Bitwarden API, e.g. of a self-hosted server, takes precedence over the region",
+        env = "BWENV_API_URL",
+        required = false
+    )]
+    pub api_url: Option<String>,
+
+    #[arg(
+        long,
+        help = "Passphrase of vault files",
+        long_help = "Passphrase of vault files, used by profiles with the vault provider and the vault commands",
+        env = "BWENV_VAULT_PASSPHRASE",
+        required = false,
+        hide_env_values = true
+    )]
+    pub vault_passphrase: Option<String>,
+}
+
+impl Cli {
+    pub fn server(&self) -> config_yaml::Server {
+        config_yaml::Server {
+            region: self.region.as_ref().map(Region::as_server_region),
+            identity_url: self.identity_url.clone(),
+            api_url: self.api_url.clone(),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+pub enum Region {
+    Us,
+    Eu,
+}
+
+impl Region {
+    pub fn as_server_region(&self) -> config_yaml::ServerRegion {
+        match self {
+            Region::Us => config_yaml::ServerRegion::Us,
+            Region::Eu => config_yaml::ServerRegion::Eu,
+        }
+    }
 }
 
 #[derive(ValueEnum, Clone, Debug)]
@@ -71,15 +182,229 @@ pub enum Command {
 
     /// Inspect the secrets of a given profile
     Inspect(InspectArgs),
+
+    #[command(subcommand)]
+    /// Manage passphrase-encrypted vault files
+    Vault(VaultCommand),
+
+    #[command(subcommand)]
+    /// Manage the secrets in the project of a given profile
+    Secret(SecretCommand),
+
+    /// Store an access token for a given profile or all profiles, encrypted with a key file of the user
+    Login,
+
+    /// Remove the stored access token of a given profile or all profiles
+    Logout,
+
+    /// Show which access token is used for a given profile
+    Whoami,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SecretCommand {
+    /// print the resolved value of a single secret
+    Get(SecretGetArgs),
+
+    /// create or update a secret
+    Set(SecretSetArgs),
+
+    /// delete a secret
+    Delete(SecretDeleteArgs),
+
+    /// rename a secret, keeping its value
+    Rename(SecretRenameArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct SecretGetArgs {
+    #[arg(help = "key of the secret")]
+    pub key: String,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "print the value without a trailing newline",
+        long_help = "print the value without a trailing newline"
+    )]
+    pub raw: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with = "raw",
+        help = "print the key and value as JSON",
+        long_help = "print the key and value as a JSON object"
+    )]
+    pub json: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct SecretSetArgs {
+    #[arg(help = "key of the secret")]
+    pub key: String,
+
+    #[arg(
+        help = "value of the secret",
+        long_help = "value of the secret, prompted for if neither the value nor --stdin is given"
+    )]
+    pub value: Option<String>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with = "value",
+        help = "read the value from stdin",
+        long_help = "read the value from stdin, a single trailing newline is removed"
+    )]
+    pub stdin: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct SecretDeleteArgs {
+    #[arg(help = "key of the secret")]
+    pub key: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct SecretRenameArgs {
+    #[arg(help = "current key of the secret")]
+    pub from: String,
+
+    #[arg(help = "new key of the secret")]
+    pub to: String,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum VaultCommand {
+    /// edit a vault in $VISUAL or $EDITOR, creating it if it does not exist
+    Edit(VaultEditArgs),
+
+    /// encrypt a dotenv, JSON or YAML file into a vault
+    Encrypt(VaultEncryptArgs),
+
+    /// decrypt a vault and print its secrets as YAML
+    Decrypt(VaultDecryptArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct VaultEditArgs {
+    #[arg(help = "vault file to edit")]
+    pub vault: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+pub struct VaultEncryptArgs {
+    #[arg(
+        help = "file to read the secrets from",
+        long_help = "file to read the secrets from, in dotenv, JSON (.json) or YAML (.yaml, .yml) format"
+    )]
+    pub input: PathBuf,
+
+    #[arg(
+        short,
+        long,
+        help = "vault file to write",
+        long_help = "vault file to write, defaults to the input file with the extension .vault"
+    )]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+pub struct VaultDecryptArgs {
+    #[arg(help = "vault file to decrypt")]
+    pub vault: PathBuf,
+
+    #[arg(
+        short,
+        long,
+        help = "file to write the secrets to",
+        long_help = "file to write the secrets to as YAML, prints them if not set"
+    )]
+    pub output: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum CacheCommand {
     /// clear the cache of a given profile
-    Clear,
+    Clear(ClearArgs),
 
     /// invalidate the cache of a given profile
     Invalidate,
+
+    /// show the state of the cache of all profiles
+    Status,
+
+    /// list the cache files of all profiles
+    List(ListArgs),
+
+    /// remove the cache of profiles that are no longer configured
+    Prune,
+
+    /// seed the cache of a given profile from a local file
+    Seed(SeedArgs),
+
+    /// refresh the cache of all or selected profiles concurrently
+    Warm(WarmArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct WarmArgs {
+    #[arg(
+        help = "profiles to refresh",
+        long_help = "profiles to refresh, defaults to all configured profiles"
+    )]
+    pub profiles: Vec<String>,
+
+    #[arg(
+        short = 'j',
+        long,
+        default_value_t = 4,
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "maximum number of profiles refreshed at the same time",
+        long_help = "maximum number of profiles refreshed at the same time"
+    )]
+    pub concurrency: u16,
+}
+
+#[derive(Parser, Debug)]
+pub struct SeedArgs {
+    #[arg(
+        long,
+        help = "file to read the secrets from",
+        long_help = "file to read the secrets from, in dotenv, JSON (.json) or YAML (.yaml, .yml) format"
+    )]
+    pub from: PathBuf,
+
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "age in seconds to record for the seeded secrets",
+        long_help = "age in seconds to record for the seeded secrets, the cache is revalidated once it exceeds the max age"
+    )]
+    pub age: u64,
+}
+
+#[derive(Parser, Debug)]
+pub struct ClearArgs {
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "clear the cache of all profiles",
+        long_help = "clear the cache of all profiles"
+    )]
+    pub all: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ListArgs {
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "print the cache files as JSON",
+        long_help = "print the cache files as JSON"
+    )]
+    pub json: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -92,4 +417,12 @@ pub struct InspectArgs {
         long_help = "reveal secrets in output"
     )]
     pub reveal: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "allow revealing secrets of a protected profile",
+        long_help = "allow revealing secrets of a protected profile"
+    )]
+    pub allow_protected: bool,
 }
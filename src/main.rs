@@ -1,31 +1,43 @@
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use cli::CacheCommand;
+use colored::Colorize;
+use futures::{stream, StreamExt};
 use semver::Version;
 use std::{
     cmp::Ordering,
+    collections::HashMap,
     io::{self, Read, Write},
-    path::Path,
+    path::{Path, PathBuf},
     process::{self, Command, Stdio},
     time,
 };
 
-use tracing::{error, info, span, warn, Level};
+use tracing::{debug, error, info, span, warn, Level};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
 mod cli;
 
 use bwenv_lib::cache;
 use bwenv_lib::config;
+use bwenv_lib::config_json;
 use bwenv_lib::config_toml;
 use bwenv_lib::config_yaml;
+use bwenv_lib::credentials::CredentialStore;
+use bwenv_lib::crypto;
 use bwenv_lib::data;
+use bwenv_lib::error::ProviderError;
+use bwenv_lib::provider;
+use bwenv_lib::schema_types::VersionReq;
+use bwenv_lib::secrets_file;
+use bwenv_lib::time::is_date_older_than_n_seconds;
+use bwenv_lib::token;
+use bwenv_lib::vault;
 use bwenv_lib::version;
-use bwenv_lib::{bitwarden, time::is_date_older_than_n_seconds};
 
 use cache::CacheEntry;
 
 use crate::cache::Cache;
-use crate::{bitwarden::BitwardenClient, cli::Cli, config_yaml::Secrets};
+use crate::{cli::Cli, config_yaml::Secrets};
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
@@ -37,13 +49,20 @@ async fn main() {
             fmt::layer()
                 .fmt_fields(fmt::format::PrettyFields::new())
                 .event_format(fmt::format().compact().without_time().with_target(false))
-                .with_writer(std::io::stdout),
+                .with_writer(log_writer(&cli)),
         )
         .init();
 
     let root_span = span!(Level::INFO, env!("CARGO_PKG_NAME"));
     let _guard = root_span.enter();
 
+    match &cli.command {
+        Some(cli::Command::Vault(vault_command)) => run_vault_command(&cli, vault_command),
+        Some(cli::Command::Login) => login(&cli),
+        Some(cli::Command::Logout) => logout(&cli),
+        _ => {}
+    }
+
     let version = Version::parse(env!("CARGO_PKG_VERSION")).unwrap();
 
     let data = data::Data::new();
@@ -52,37 +71,67 @@ async fn main() {
     let latest_version = if is_date_older_than_n_seconds(data_content.last_update_check, &86400_u64)
         || data_content.last_checked_version.is_none()
     {
-        let latest_version = version::fetch_latest_version().await.unwrap();
-        let _ = data.set_content(
-            time::SystemTime::now()
-                .duration_since(time::SystemTime::UNIX_EPOCH)
-                .expect("SystemTime before UNIX EPOCH!")
-                .as_millis()
-                .try_into()
-                .unwrap(),
-            Version::to_string(&latest_version),
-        );
-        latest_version.to_string()
+        // The check is best effort, e.g. offline runs fall back to the cache instead
+        match version::fetch_latest_version().await {
+            Ok(latest_version) => {
+                let _ = data.set_content(
+                    time::SystemTime::now()
+                        .duration_since(time::SystemTime::UNIX_EPOCH)
+                        .expect("SystemTime before UNIX EPOCH!")
+                        .as_millis()
+                        .try_into()
+                        .unwrap(),
+                    Version::to_string(&latest_version),
+                );
+                Some(latest_version)
+            }
+            Err(err) => {
+                debug!(message = format!("Could not check for a new version: {}", err));
+                None
+            }
+        }
     } else {
-        data_content.last_checked_version.unwrap()
+        data_content
+            .last_checked_version
+            .and_then(|latest_version| Version::parse(&latest_version).ok())
     };
-    let latest_version = Version::parse(&latest_version).unwrap();
-    let ordering = version.cmp_precedence(&latest_version);
-    if ordering == Ordering::Less {
-        info!(message = format!("New version available: {}", &latest_version));
+    if let Some(latest_version) = latest_version {
+        if version.cmp_precedence(&latest_version) == Ordering::Less {
+            info!(message = format!("New version available: {}", &latest_version));
+        }
     }
 
-    let local_config = config::find_local_config(Some(&std::env::current_dir().unwrap())).unwrap();
+    let cwd = std::env::current_dir().unwrap();
+    let local_config = match &cli.config {
+        Some(config_path) => config::LocalConfig::from_path(&cwd.join(config_path)),
+        None => config::find_local_config(Some(&cwd)),
+    }
+    .unwrap_or_else(|err| {
+        error!(message = format!("Could not load configuration file: {:?}", err));
+        process::exit(1)
+    });
 
     let config_path = local_config.as_pathbuf();
 
+    fn invalid_config(err: impl std::fmt::Display) -> ! {
+        error!(message = format!("Invalid configuration file: {}", err));
+        process::exit(1)
+    }
+
     match local_config {
         config::LocalConfig::Yaml(_) => {
-            let config = config_yaml::Config::new(config_path).unwrap();
+            let config =
+                config_yaml::Config::new(config_path).unwrap_or_else(|err| invalid_config(err));
+            run_with(cli, config_path, config, version).await
+        }
+        config::LocalConfig::Json(_) => {
+            let config = config_json::parse_config_file(config_path)
+                .unwrap_or_else(|err| invalid_config(err));
             run_with(cli, config_path, config, version).await
         }
         config::LocalConfig::Toml(_) => {
-            let toml_config = config_toml::Config::new(config_path).unwrap();
+            let toml_config =
+                config_toml::Config::new(config_path).unwrap_or_else(|err| invalid_config(err));
             let config = toml_config.as_yaml_config();
             warn!("bwenv.toml is deprecated. Please migrate to bwenv.yaml");
             run_with(cli, config_path, config, version).await
@@ -90,6 +139,106 @@ async fn main() {
     };
 }
 
+/// Reads the token passed on the command line, --token, --token-stdin and --token-file are
+/// mutually exclusive
+fn cli_token(cli: &Cli) -> Option<String> {
+    let token = if let Some(token) = &cli.token {
+        Ok(token.clone())
+    } else if cli.token_stdin {
+        token::read_stdin()
+    } else if let Some(token_file) = &cli.token_file {
+        token::read_file(token_file)
+    } else {
+        return None;
+    };
+
+    Some(token.unwrap_or_else(|err| {
+        error!(message = format!("{}", err));
+        process::exit(1)
+    }))
+}
+
+/// Resolves the access token from the command line, falling back to the token sources
+/// of the configuration file, returning the token and where it was read from
+fn resolve_token(
+    cli_token: Option<&str>,
+    token_sources: &[token::Source],
+    root_dir: &Path,
+) -> Result<Option<(String, String)>, String> {
+    match cli_token {
+        Some(token) => Ok(Some((token.to_string(), String::from("command line")))),
+        None => token::resolve(token_sources, root_dir)
+            .map(|resolved| resolved.map(|(token, source)| (token, source.to_string())))
+            .map_err(|err| err.to_string()),
+    }
+}
+
+fn token_envs<'t>(token_sources: &'t [token::Source]) -> Vec<&'t str> {
+    token_sources
+        .iter()
+        .filter_map(|source| match source {
+            token::Source::Env(name) => Some(*name),
+            _ => None,
+        })
+        .collect()
+}
+
+fn missing_token(token_sources: &[token::Source]) -> ! {
+    Cli::command()
+        .error(
+            clap::error::ErrorKind::MissingRequiredArgument,
+            format!(
+                "The following required argument was not provided: token\n\n\
+                 Pass --token, --token-file or --token-stdin, set {}, run bwenv login or configure a token-command",
+                token_envs(token_sources).join(" or ")
+            ),
+        )
+        .exit()
+}
+
+/// Returns the secret keying the cache of a profile, and encrypting it without a key file:
+/// the access token for Bitwarden, a key derived from the vault and the path of a file. The
+/// path is no secret, so only a key file protects encrypted caches of file profiles
+fn cache_secret(
+    provider_kind: &config_yaml::ProviderKind,
+    token: &str,
+    project_id: &str,
+    root_dir: &Path,
+    vault_passphrase: Option<&str>,
+) -> Result<String, String> {
+    match provider_kind {
+        config_yaml::ProviderKind::Bitwarden => Ok(token.to_string()),
+        config_yaml::ProviderKind::File => Ok(root_dir.join(project_id).display().to_string()),
+        config_yaml::ProviderKind::Vault => {
+            let passphrase = vault_passphrase.ok_or_else(|| {
+                String::from(
+                    "No vault passphrase, pass --vault-passphrase or set BWENV_VAULT_PASSPHRASE",
+                )
+            })?;
+            vault::cache_secret(&root_dir.join(project_id), passphrase)
+                .map_err(|err| err.to_string())
+        }
+    }
+}
+
+fn require_version(version_req: &VersionReq, version: &Version) {
+    if !version_req.matches(version) {
+        error!(
+            "Version {} does not meet the requirement {}",
+            version, version_req
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Bitwarden sessions are persisted next to the user cache unless caching is disabled
+fn auth_state_dir(config: &config_yaml::Config) -> Option<PathBuf> {
+    match config.cache.mode {
+        config_yaml::CacheMode::None => None,
+        _ => cache::auth_state_directory(),
+    }
+}
+
 async fn run_with<'a>(
     cli: Cli,
     config_path: &Path,
@@ -109,7 +258,166 @@ async fn run_with<'a>(
     }
 
     let root_dir = config_path.parent().unwrap();
-    let cache_dir = root_dir.join(config.cache.path.as_path());
+    let cli_token = cli_token(&cli);
+    let cache_dir = match config.cache.location {
+        config_yaml::CacheLocation::Project => root_dir.join(config.cache.path.as_path()),
+        config_yaml::CacheLocation::User => cache::user_cache_directory().unwrap_or_else(|| {
+            error!(message = "Could not find the cache directory of the user");
+            process::exit(1)
+        }),
+    };
+
+    let storage_for = |token: &str| match config.cache.mode {
+        config_yaml::CacheMode::Plain => cache::Storage::Plain,
+        config_yaml::CacheMode::None => cache::Storage::None,
+        config_yaml::CacheMode::Encrypted => match &config.cache.key_file {
+            Some(key_file) => {
+                let key = std::fs::read(root_dir.join(key_file)).unwrap_or_else(|_| {
+                    error!(message = format!("Could not read cache key file {:?}", key_file));
+                    process::exit(1)
+                });
+                cache::Storage::encrypted(&key)
+            }
+            None => cache::Storage::encrypted(token.as_bytes()),
+        },
+    };
+    let new_cache = |token: &str, project_ids: Vec<String>| {
+        Cache::new(
+            cache_dir.clone(),
+            &version,
+            storage_for(token),
+            cache::Identity::new(project_ids, token),
+        )
+    };
+
+    if let Some(cli::Command::Cache(cache_command)) = &cli.command {
+        let global_cache = || {
+            Cache::new(
+                cache_dir.clone(),
+                &version,
+                cache::Storage::Plain,
+                cache::Identity::default(),
+            )
+        };
+        // The secret of each profile is only needed to decrypt and verify its entries. Token
+        // commands are not run for every profile, entries of profiles without a secret at hand
+        // are described without checking their integrity
+        let profile_cache = |profile: &str| {
+            let secret = match config.evaluate(profile) {
+                Ok(evaluation) if evaluation.provider != config_yaml::ProviderKind::Bitwarden => {
+                    cache_secret(
+                        &evaluation.provider,
+                        "",
+                        evaluation.project_id,
+                        root_dir,
+                        cli.vault_passphrase.as_deref(),
+                    )
+                    .ok()
+                }
+                evaluation => {
+                    let token_sources: Vec<token::Source> = evaluation
+                        .map(|evaluation| evaluation.token_sources)
+                        .unwrap_or_else(|_| config.token_sources())
+                        .into_iter()
+                        .filter(|source| !matches!(source, token::Source::Command(_)))
+                        .collect();
+                    resolve_token(cli_token.as_deref(), &token_sources, root_dir)
+                        .ok()
+                        .flatten()
+                        .map(|(token, _)| token)
+                }
+            };
+            match secret {
+                Some(secret) => new_cache(&secret, vec![]),
+                None => {
+                    let storage = match (config.cache.mode, &config.cache.key_file) {
+                        // Entries encrypted with the token are unreadable without it
+                        (config_yaml::CacheMode::Encrypted, None) => cache::Storage::Plain,
+                        _ => storage_for(""),
+                    };
+                    Cache::new(
+                        cache_dir.clone(),
+                        &version,
+                        storage,
+                        cache::Identity::default(),
+                    )
+                }
+            }
+        };
+        let statuses = || -> Vec<cache::CacheStatus> {
+            global_cache()
+                .profiles()
+                .iter()
+                .flat_map(|profile| {
+                    profile_cache(profile).profile_status(profile, &config.cache.max_age)
+                })
+                .collect()
+        };
+        match cache_command {
+            CacheCommand::Clear(clear_args) if clear_args.all => {
+                if config.cache.location == config_yaml::CacheLocation::User {
+                    error!(
+                        message = "Clearing all profiles is not supported for caches shared between projects, clear them one by one"
+                    );
+                    process::exit(1);
+                }
+                global_cache().clear_all();
+                process::exit(0);
+            }
+            CacheCommand::Status => {
+                print!("{}", cache::status_table(&statuses()));
+                process::exit(0);
+            }
+            CacheCommand::List(list_args) => {
+                let statuses = statuses();
+                if list_args.json {
+                    println!("{}", serde_json::to_string_pretty(&statuses).unwrap());
+                } else {
+                    for status in statuses {
+                        println!(
+                            "{}\t{}\t{}",
+                            status.profile,
+                            status.project,
+                            status.path.display()
+                        );
+                    }
+                }
+                process::exit(0);
+            }
+            CacheCommand::Prune => {
+                if config.cache.location == config_yaml::CacheLocation::User {
+                    error!(message = "Pruning is not supported for caches shared between projects");
+                    process::exit(1);
+                }
+                let profiles: Vec<&str> = config.profiles.keys().map(String::as_str).collect();
+                global_cache().prune(&profiles);
+                process::exit(0);
+            }
+            CacheCommand::Warm(warm_args) => {
+                // Caches written by an incompatible version would be read by compatible ones
+                require_version(&config.version, &version);
+                let context = provider::ProviderContext {
+                    token: String::new(),
+                    server_urls: cli.server().urls(),
+                    root_dir: root_dir.to_path_buf(),
+                    vault_passphrase: cli.vault_passphrase.clone(),
+                    auth_state_dir: auth_state_dir(&config),
+                    fetch: config.fetch,
+                };
+                let succeeded = warm_caches(
+                    cli_token.as_deref(),
+                    &context,
+                    &cli.server(),
+                    &config,
+                    &new_cache,
+                    warm_args,
+                )
+                .await;
+                process::exit(if succeeded { 0 } else { 1 });
+            }
+            _ => {}
+        }
+    }
 
     let profile_name = cli.profile.clone().unwrap_or_else(|| {
         info!(message = "No profile specified, falling back to default profile");
@@ -121,6 +429,10 @@ async fn run_with<'a>(
         max_age,
         project_id,
         overrides,
+        protected,
+        server,
+        provider: provider_kind,
+        token_sources,
         ..
     } = config.evaluate(&profile_name).unwrap_or_else(|_| {
         error!(
@@ -132,11 +444,79 @@ async fn run_with<'a>(
         process::exit(1)
     });
 
-    let cache = Cache::new(cache_dir, &version);
+    if protected {
+        print_protected_banner(&profile_name);
+
+        // Confirm before resolving the token and secrets, so declining neither reaches the
+        // provider nor writes the cache
+        match &cli.command {
+            Some(cli::Command::Inspect(inspect_args))
+                if inspect_args.reveal && !inspect_args.allow_protected =>
+            {
+                error!(
+                    message = format!(
+                        "Refusing to reveal secrets of protected profile {:?}, pass --allow-protected to override",
+                        profile_name
+                    )
+                );
+                process::exit(1);
+            }
+            Some(cli::Command::Secret(cli::SecretCommand::Get(_))) if !cli.yes => {
+                confirm_protected_run(&profile_name, "secret get");
+            }
+            None if !cli.yes => {
+                if let Some((program, _)) = get_program(&cli) {
+                    confirm_protected_run(&profile_name, &program);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Only Bitwarden authenticates with the access token, other providers need it when it is
+    // shown or passed to the command
+    let needs_token = provider_kind == config_yaml::ProviderKind::Bitwarden
+        || cli.pass_token
+        || matches!(cli.command, Some(cli::Command::Whoami));
+    let (token, token_source) = match needs_token {
+        true => match resolve_token(cli_token.as_deref(), &token_sources, root_dir) {
+            Ok(Some(resolved)) => resolved,
+            Ok(None) => missing_token(&token_sources),
+            Err(err) => {
+                error!(message = format!("{}", err));
+                process::exit(1)
+            }
+        },
+        false => (String::new(), String::new()),
+    };
+
+    if let Some(cli::Command::Whoami) = &cli.command {
+        println!("profile      {}", profile_name);
+        println!("source       {}", token_source);
+        println!(
+            "token id     {}",
+            token::access_token_id(&token).unwrap_or("unknown, the token is malformed")
+        );
+        println!("fingerprint  {}", crypto::fingerprint(token.as_bytes()));
+        process::exit(0);
+    }
+
+    let secret = cache_secret(
+        &provider_kind,
+        &token,
+        project_id,
+        root_dir,
+        cli.vault_passphrase.as_deref(),
+    )
+    .unwrap_or_else(|err| {
+        error!(message = format!("{}", err));
+        process::exit(1)
+    });
+    let cache = new_cache(&secret, vec![project_id.to_string()]);
 
     match &cli.command {
         Some(cli::Command::Cache(cache_command)) => match cache_command {
-            CacheCommand::Clear => {
+            CacheCommand::Clear(_) => {
                 cache.clear(&profile_name);
                 process::exit(0);
             }
@@ -144,34 +524,107 @@ async fn run_with<'a>(
                 cache.invalidate(&profile_name);
                 process::exit(0);
             }
+            CacheCommand::Seed(seed_args) => {
+                let secrets = secrets_file::read(&seed_args.from).unwrap_or_else(|err| {
+                    error!(message = format!("{}", err));
+                    process::exit(1)
+                });
+                cache.seed(&profile_name, secrets, seed_args.age);
+                process::exit(0);
+            }
+            _ => {}
         },
         None => {}
         Some(_) => {}
     }
 
-    if !version_req.matches(&version) {
-        error!(
-            "Version {} does not meet the requirement {}",
-            version, version_req
-        );
-        std::process::exit(1);
+    require_version(&version_req, &version);
+
+    let provider_context = provider::ProviderContext {
+        token: token.clone(),
+        server_urls: server.merge(&cli.server()).urls(),
+        root_dir: root_dir.to_path_buf(),
+        vault_passphrase: cli.vault_passphrase.clone(),
+        auth_state_dir: auth_state_dir(&config),
+        fetch: config.fetch,
+    };
+
+    match &cli.command {
+        Some(cli::Command::Secret(cli::SecretCommand::Get(_))) => {}
+        Some(cli::Command::Secret(secret_command)) => {
+            change_secret(
+                &cli,
+                secret_command,
+                &profile_name,
+                project_id,
+                &provider_kind,
+                &provider_context,
+            )
+            .await;
+            cache.invalidate(&profile_name);
+            process::exit(0);
+        }
+        _ => {}
     }
 
-    let token = cli.token.clone();
-    let CacheEntry { variables, .. } = cache
-        .get_or_revalidate(&profile_name, max_age, move || async move {
-            let mut bitwarden_client = BitwardenClient::new(token).await;
-            bitwarden_client
-                .get_secrets_by_project_id(&project_id)
-                .await
-                .unwrap()
-        })
+    let fallback = match config.cache.fallback {
+        config_yaml::CacheFallback::Stale => cache::Fallback::Stale {
+            max_stale: config.cache.max_stale,
+        },
+        config_yaml::CacheFallback::Strict => cache::Fallback::Strict,
+    };
+
+    let revalidation = match config.cache.revalidation {
+        config_yaml::CacheRevalidation::Blocking => cache::Revalidation::Blocking,
+        config_yaml::CacheRevalidation::Background => cache::Revalidation::Background {
+            timeout: time::Duration::from_secs(*config.cache.revalidation_timeout),
+        },
+    };
+
+    let (
+        CacheEntry {
+            variables,
+            metadata,
+            seeded,
+            ..
+        },
+        background_revalidation,
+    ) = cache
+        .get_or_revalidate(
+            &profile_name,
+            max_age,
+            &fallback,
+            &revalidation,
+            move || async move {
+                provider::connect(&provider_kind, &provider_context)
+                    .await?
+                    .fetch_with_metadata(project_id)
+                    .await
+            },
+        )
         .await
-        .unwrap();
+        .unwrap_or_else(|err| {
+            error!(
+                message = format!(
+                    "Failed to fetch secrets for profile {:?}: {}",
+                    profile_name, err
+                )
+            );
+            process::exit(err.exit_code())
+        });
 
     let mut secrets = Secrets::merge(&variables, &overrides);
 
     if let Some(cli::Command::Inspect(inspect_args)) = &cli.command {
+        if seeded {
+            warn!(
+                message = format!(
+                    "Secrets of profile {:?} were seeded manually from a local file",
+                    profile_name
+                )
+            );
+        }
+
         let is_terminal = atty::is(atty::Stream::Stdout);
         let reveal = if inspect_args.reveal && is_terminal {
                 inquire::Confirm::new("reveal secrets in output")
@@ -183,7 +636,40 @@ async fn run_with<'a>(
             }
             .unwrap();
 
-        print!("{}", &secrets.table(reveal));
+        print!("{}", &secrets.table(reveal, &metadata));
+        if let Some(background_revalidation) = background_revalidation {
+            background_revalidation.await;
+        }
+        process::exit(0);
+    }
+
+    if let Some(cli::Command::Secret(cli::SecretCommand::Get(get_args))) = &cli.command {
+        let value = secrets.get(get_args.key.as_str()).unwrap_or_else(|| {
+            let err = ProviderError::SecretNotFound(get_args.key.clone());
+            error!(
+                message = format!(
+                    "Could not get secret of profile {:?}: {}",
+                    profile_name, err
+                )
+            );
+            process::exit(err.exit_code())
+        });
+
+        if get_args.json {
+            println!(
+                "{}",
+                serde_json::json!({ "key": get_args.key, "value": value })
+            );
+        } else if get_args.raw {
+            print!("{}", value);
+        } else {
+            println!("{}", value);
+        }
+        io::stdout().flush().expect("Failed to flush stdout");
+
+        if let Some(background_revalidation) = background_revalidation {
+            background_revalidation.await;
+        }
         process::exit(0);
     }
 
@@ -196,6 +682,14 @@ async fn run_with<'a>(
     };
 
     let mut cmd = Command::new(program);
+    // Tokens are not inherited by the command unless requested, secrets of the same name still apply
+    cmd.env_remove(token::DEFAULT_ENV);
+    for token_env in token_envs(&token_sources) {
+        cmd.env_remove(token_env);
+    }
+    if cli.pass_token {
+        cmd.env(token::DEFAULT_ENV, &token);
+    }
     cmd.envs(secrets.as_vec());
     cmd.args(program_args);
     cmd.stdin(Stdio::inherit())
@@ -240,11 +734,503 @@ async fn run_with<'a>(
             }
         });
 
-        // Wait for the child process to finish and close the threads
-        let status = child.wait().expect("Failed to wait on child process");
+        // Wait for the child process to finish while the cache is revalidated in the background
+        let (status, _) = tokio::join!(tokio::task::spawn_blocking(move || child.wait()), async {
+            if let Some(background_revalidation) = background_revalidation {
+                background_revalidation.await;
+            }
+        });
+        let status = status
+            .expect("Failed to join child process")
+            .expect("Failed to wait on child process");
+
+        // Close the threads
         stdout_thread.join().expect("stdout thread panicked");
         stderr_thread.join().expect("stderr thread panicked");
 
         process::exit(status.code().unwrap_or(1))
     }
 }
+
+type ProvidersByServer = HashMap<
+    (config_yaml::ProviderKind, config_yaml::ServerUrls, String),
+    Result<Box<dyn provider::SecretsProvider>, String>,
+>;
+
+async fn warm_caches<'v>(
+    cli_token: Option<&str>,
+    context: &provider::ProviderContext,
+    server: &config_yaml::Server,
+    config: &config_yaml::Config<'_>,
+    new_cache: &dyn Fn(&str, Vec<String>) -> Cache<'v>,
+    warm_args: &cli::WarmArgs,
+) -> bool {
+    if config.cache.mode == config_yaml::CacheMode::None {
+        error!(message = "Caching is disabled by mode none, nothing to warm");
+        return false;
+    }
+
+    let profiles: Vec<String> = if warm_args.profiles.is_empty() {
+        let mut profiles: Vec<String> = config.profiles.keys().cloned().collect();
+        profiles.sort();
+        profiles
+    } else {
+        warm_args.profiles.clone()
+    };
+
+    // Resolve tokens sequentially, token commands may prompt. Only Bitwarden profiles need one
+    let mut tokens: HashMap<String, Result<String, String>> = HashMap::new();
+    for profile_name in &profiles {
+        if let Ok(evaluation) = config.evaluate(profile_name) {
+            if evaluation.provider != config_yaml::ProviderKind::Bitwarden {
+                tokens.insert(profile_name.clone(), Ok(String::new()));
+                continue;
+            }
+            let token = match resolve_token(cli_token, &evaluation.token_sources, &context.root_dir)
+            {
+                Ok(Some((token, _))) => Ok(token),
+                Ok(None) => Err(format!(
+                    "no access token, set {}",
+                    token_envs(&evaluation.token_sources).join(" or ")
+                )),
+                Err(err) => Err(err),
+            };
+            tokens.insert(profile_name.clone(), token);
+        }
+    }
+
+    // Log in once per provider, server and token, profiles of the same service account
+    // share the provider
+    let mut providers: ProvidersByServer = HashMap::new();
+    for profile_name in &profiles {
+        if let (Ok(evaluation), Some(Ok(token))) =
+            (config.evaluate(profile_name), tokens.get(profile_name))
+        {
+            let server_urls = evaluation.server.merge(server).urls();
+            let key = (evaluation.provider, server_urls.clone(), token.clone());
+            if !providers.contains_key(&key) {
+                let context = provider::ProviderContext {
+                    token: token.clone(),
+                    server_urls,
+                    ..context.clone()
+                };
+                let provider = provider::connect(&evaluation.provider, &context)
+                    .await
+                    .map_err(|err| format!("login failed: {}", err));
+                providers.insert(key, provider);
+            }
+        }
+    }
+
+    let mut results: Vec<(String, Result<usize, String>)> = stream::iter(profiles)
+        .map(|profile_name| {
+            let providers = &providers;
+            let tokens = &tokens;
+            async move {
+                let result = match config.evaluate(&profile_name) {
+                    Ok(evaluation) => {
+                        let token = match &tokens[&profile_name] {
+                            Ok(token) => token,
+                            Err(err) => return (profile_name.clone(), Err(err.clone())),
+                        };
+                        let key = (
+                            evaluation.provider,
+                            evaluation.server.merge(server).urls(),
+                            token.clone(),
+                        );
+                        let provider = match &providers[&key] {
+                            Ok(provider) => provider,
+                            Err(err) => return (profile_name.clone(), Err(err.clone())),
+                        };
+                        let secret = match cache_secret(
+                            &evaluation.provider,
+                            token,
+                            evaluation.project_id,
+                            &context.root_dir,
+                            context.vault_passphrase.as_deref(),
+                        ) {
+                            Ok(secret) => secret,
+                            Err(err) => return (profile_name.clone(), Err(err)),
+                        };
+                        let cache = new_cache(&secret, vec![evaluation.project_id.to_string()]);
+                        cache
+                            .refresh(&profile_name, || {
+                                provider.fetch_with_metadata(evaluation.project_id)
+                            })
+                            .await
+                            .map(|cache_entry| cache_entry.variables.len())
+                            .map_err(|err| err.to_string())
+                    }
+                    Err(_) => Err(String::from("profile is not configured")),
+                };
+                (profile_name, result)
+            }
+        })
+        .buffer_unordered(usize::from(warm_args.concurrency))
+        .collect()
+        .await;
+
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    let failures = results.iter().filter(|(_, result)| result.is_err()).count();
+    for (profile_name, result) in &results {
+        match result {
+            Ok(keys) => println!("{} {} ({} keys)", "ok".green().bold(), profile_name, keys),
+            Err(err) => println!("{} {} ({})", "failed".red().bold(), profile_name, err),
+        }
+    }
+    println!(
+        "{} profiles warmed, {} failed",
+        results.len() - failures,
+        failures
+    );
+
+    failures == 0
+}
+
+/// Logs go to stdout, except for commands whose output is meant to be consumed by scripts
+fn log_writer(cli: &Cli) -> fmt::writer::BoxMakeWriter {
+    match &cli.command {
+        Some(cli::Command::Secret(cli::SecretCommand::Get(_))) => {
+            fmt::writer::BoxMakeWriter::new(std::io::stderr)
+        }
+        _ => fmt::writer::BoxMakeWriter::new(std::io::stdout),
+    }
+}
+
+fn print_protected_banner(profile_name: &str) {
+    let message = format!("  PROTECTED PROFILE {:?}  ", profile_name);
+    let border = "=".repeat(message.len());
+    eprintln!("{}", border.red().bold());
+    eprintln!("{}", message.red().bold());
+    eprintln!("{}", border.red().bold());
+}
+
+fn confirm_protected_run(profile_name: &str, program: &str) {
+    if !atty::is(atty::Stream::Stdin) {
+        error!(
+            message = format!(
+                "Profile {:?} is protected, pass --yes to run it in a non-interactive context",
+                profile_name
+            )
+        );
+        process::exit(1);
+    }
+
+    let confirmed = inquire::Confirm::new(&format!(
+        "run {:?} using protected profile {:?}",
+        program, profile_name
+    ))
+    .with_default(false)
+    .prompt()
+    .unwrap_or(false);
+
+    if !confirmed {
+        error!(message = "Aborted");
+        process::exit(1);
+    }
+}
+
+async fn change_secret(
+    cli: &Cli,
+    secret_command: &cli::SecretCommand,
+    profile_name: &str,
+    project_id: &str,
+    provider_kind: &config_yaml::ProviderKind,
+    context: &provider::ProviderContext,
+) {
+    let change = match secret_command {
+        cli::SecretCommand::Set(set_args) => format!("set secret {:?}", set_args.key),
+        cli::SecretCommand::Delete(delete_args) => format!("delete secret {:?}", delete_args.key),
+        cli::SecretCommand::Rename(rename_args) => format!(
+            "rename secret {:?} to {:?}",
+            rename_args.from, rename_args.to
+        ),
+        cli::SecretCommand::Get(_) => unreachable!("getting a secret does not change it"),
+    };
+
+    // Read the value before confirming, the prompt must not consume a piped value
+    let value = match secret_command {
+        cli::SecretCommand::Set(set_args) => secret_value(cli, set_args),
+        _ => String::new(),
+    };
+
+    if !cli.yes {
+        confirm_secret_change(&change, profile_name);
+    }
+
+    let result = async {
+        let provider = provider::connect(provider_kind, context).await?;
+        match secret_command {
+            cli::SecretCommand::Set(set_args) => {
+                provider.set(project_id, &set_args.key, &value).await
+            }
+            cli::SecretCommand::Delete(delete_args) => {
+                provider.delete(project_id, &delete_args.key).await
+            }
+            cli::SecretCommand::Rename(rename_args) => {
+                provider
+                    .rename(project_id, &rename_args.from, &rename_args.to)
+                    .await
+            }
+            cli::SecretCommand::Get(_) => unreachable!("getting a secret does not change it"),
+        }
+    }
+    .await;
+
+    if let Err(err) = result {
+        error!(
+            message = format!(
+                "Could not {} of profile {:?}: {}",
+                change, profile_name, err
+            )
+        );
+        process::exit(err.exit_code());
+    }
+    info!(message = format!("Done: {} of profile {:?}", change, profile_name));
+}
+
+fn secret_value(cli: &Cli, set_args: &cli::SecretSetArgs) -> String {
+    if let Some(value) = &set_args.value {
+        return value.clone();
+    }
+
+    if set_args.stdin && cli.token_stdin {
+        error!(message = "The value and the access token can not both be read from stdin");
+        process::exit(1);
+    }
+
+    if set_args.stdin {
+        let mut value = String::new();
+        io::stdin()
+            .read_to_string(&mut value)
+            .unwrap_or_else(|err| {
+                error!(message = format!("Could not read the value from stdin: {}", err));
+                process::exit(1)
+            });
+        let value = value.strip_suffix('\n').unwrap_or(&value);
+        return value.strip_suffix('\r').unwrap_or(value).to_string();
+    }
+
+    if !atty::is(atty::Stream::Stdin) {
+        error!(message = "No value given, pass it as an argument or use --stdin");
+        process::exit(1);
+    }
+
+    inquire::Password::new(&format!("value of {:?}", set_args.key))
+        .prompt()
+        .unwrap_or_else(|_| {
+            error!(message = "Aborted");
+            process::exit(1)
+        })
+}
+
+fn confirm_secret_change(change: &str, profile_name: &str) {
+    if !atty::is(atty::Stream::Stdin) {
+        error!(
+            message = format!(
+                "Refusing to {} of profile {:?} in a non-interactive context, pass --yes to confirm",
+                change, profile_name
+            )
+        );
+        process::exit(1);
+    }
+
+    let confirmed = inquire::Confirm::new(&format!("{} of profile {:?}", change, profile_name))
+        .with_default(false)
+        .prompt()
+        .unwrap_or(false);
+
+    if !confirmed {
+        error!(message = "Aborted");
+        process::exit(1);
+    }
+}
+
+fn credential_store() -> CredentialStore {
+    CredentialStore::user().unwrap_or_else(|| {
+        error!(message = "Could not find the data directory of the user");
+        process::exit(1)
+    })
+}
+
+fn login_target(cli: &Cli) -> String {
+    match &cli.profile {
+        Some(profile) => format!("profile {:?}", profile),
+        None => String::from("all profiles"),
+    }
+}
+
+fn login(cli: &Cli) -> ! {
+    let token = cli_token(cli).unwrap_or_else(|| {
+        if !atty::is(atty::Stream::Stdin) {
+            error!(message = "No access token, pass --token, --token-file or --token-stdin");
+            process::exit(1);
+        }
+
+        inquire::Password::new(&format!("access token for {}", login_target(cli)))
+            .with_display_mode(inquire::PasswordDisplayMode::Masked)
+            .without_confirmation()
+            .prompt()
+            .unwrap_or_else(|_| {
+                error!(message = "Aborted");
+                process::exit(1)
+            })
+    });
+    let token = token.trim();
+
+    let token_id = token::access_token_id(token).unwrap_or_else(|| {
+        error!(message = "This is not an access token of a service account");
+        process::exit(1)
+    });
+
+    if let Err(err) = credential_store().set(cli.profile.as_deref(), token) {
+        error!(message = format!("Could not store the access token: {}", err));
+        process::exit(1);
+    }
+    info!(message = format!("Stored access token {} for {}", token_id, login_target(cli)));
+    process::exit(0)
+}
+
+fn logout(cli: &Cli) -> ! {
+    match credential_store().remove(cli.profile.as_deref()) {
+        Ok(true) => info!(message = format!("Removed the access token of {}", login_target(cli))),
+        Ok(false) => {
+            info!(message = format!("No access token is stored for {}", login_target(cli)))
+        }
+        Err(err) => {
+            error!(message = format!("Could not remove the access token: {}", err));
+            process::exit(1);
+        }
+    }
+    process::exit(0)
+}
+
+fn run_vault_command(cli: &Cli, vault_command: &cli::VaultCommand) -> ! {
+    let result = match vault_command {
+        cli::VaultCommand::Edit(edit_args) => edit_vault(cli, edit_args),
+        cli::VaultCommand::Encrypt(encrypt_args) => encrypt_vault(cli, encrypt_args),
+        cli::VaultCommand::Decrypt(decrypt_args) => decrypt_vault(cli, decrypt_args),
+    };
+
+    if let Err(err) = result {
+        error!(message = format!("{}", err));
+        process::exit(1);
+    }
+    process::exit(0)
+}
+
+fn vault_passphrase(cli: &Cli, confirm: bool) -> String {
+    if let Some(vault_passphrase) = &cli.vault_passphrase {
+        return vault_passphrase.clone();
+    }
+
+    if !atty::is(atty::Stream::Stdin) {
+        error!(
+            message = "No vault passphrase, pass --vault-passphrase or set BWENV_VAULT_PASSPHRASE"
+        );
+        process::exit(1);
+    }
+
+    let prompt = inquire::Password::new("vault passphrase");
+    let prompt = if confirm {
+        prompt
+    } else {
+        prompt.without_confirmation()
+    };
+    prompt.prompt().unwrap_or_else(|_| {
+        error!(message = "Aborted");
+        process::exit(1)
+    })
+}
+
+fn edit_vault(cli: &Cli, edit_args: &cli::VaultEditArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let (plaintext, passphrase) = if edit_args.vault.exists() {
+        let passphrase = vault_passphrase(cli, false);
+        let plaintext = vault::open(&std::fs::read_to_string(&edit_args.vault)?, &passphrase)?;
+        (plaintext, passphrase)
+    } else {
+        (String::from("# KEY: value\n"), vault_passphrase(cli, true))
+    };
+
+    let mut file = tempfile::Builder::new()
+        .prefix("bwenv-vault-")
+        .suffix(".yaml")
+        .tempfile()?;
+    file.write_all(plaintext.as_bytes())?;
+    file.flush()?;
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    let mut editor = editor.split_whitespace();
+    let status = Command::new(editor.next().unwrap_or("vi"))
+        .args(editor)
+        .arg(file.path())
+        .status()?;
+    if !status.success() {
+        return Err("Editor exited with an error, the vault was not changed".into());
+    }
+
+    let edited = std::fs::read_to_string(file.path())?;
+    if edited == plaintext && edit_args.vault.exists() {
+        info!(message = format!("Vault {:?} was not changed", edit_args.vault));
+        return Ok(());
+    }
+
+    // Keep the edits when they can not be sealed, e.g. invalid YAML, instead of dropping them
+    // with the temporary file
+    let sealed = match vault::seal(&edited, &passphrase) {
+        Ok(sealed) => sealed,
+        Err(err) => {
+            let (_, path) = file.keep()?;
+            return Err(format!(
+                "{}, the vault was not changed and the edits were kept in {:?}",
+                err, path
+            )
+            .into());
+        }
+    };
+    vault::write(&edit_args.vault, &sealed)?;
+    info!(message = format!("Saved vault {:?}", edit_args.vault));
+    Ok(())
+}
+
+fn encrypt_vault(
+    cli: &Cli,
+    encrypt_args: &cli::VaultEncryptArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let secrets = secrets_file::read(&encrypt_args.input)?;
+    let output = encrypt_args
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("{}.vault", encrypt_args.input.display())));
+
+    let passphrase = vault_passphrase(cli, true);
+    vault::write(
+        &output,
+        &vault::seal(&vault::to_plaintext(&secrets), &passphrase)?,
+    )?;
+    info!(
+        message = format!(
+            "Encrypted {} secrets from {:?} into {:?}",
+            secrets.len(),
+            encrypt_args.input,
+            output
+        )
+    );
+    Ok(())
+}
+
+fn decrypt_vault(
+    cli: &Cli,
+    decrypt_args: &cli::VaultDecryptArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let passphrase = vault_passphrase(cli, false);
+    let plaintext = vault::open(&std::fs::read_to_string(&decrypt_args.vault)?, &passphrase)?;
+
+    match &decrypt_args.output {
+        Some(output) => std::fs::write(output, plaintext)?,
+        None => print!("{}", plaintext),
+    }
+    Ok(())
+}
@@ -102,3 +102,143 @@ fn prints_values_custom_profile() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn prints_values_from_file_provider() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bwenv")?;
+    cmd.env_remove("BWS_ACCESS_TOKEN")
+        .arg("--profile")
+        .arg("local")
+        .arg("inspect")
+        .arg("--reveal");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("TEST_VALUE :: local"));
+
+    Ok(())
+}
+
+#[test]
+fn prints_single_secret() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bwenv")?;
+    cmd.env_remove("BWS_ACCESS_TOKEN")
+        .arg("--profile")
+        .arg("local")
+        .arg("secret")
+        .arg("get")
+        .arg("TEST_VALUE");
+
+    cmd.assert().success().stdout("local\n");
+
+    let mut cmd = Command::cargo_bin("bwenv")?;
+    cmd.env_remove("BWS_ACCESS_TOKEN")
+        .arg("--profile")
+        .arg("local")
+        .arg("secret")
+        .arg("get")
+        .arg("--json")
+        .arg("TEST_VALUE");
+
+    cmd.assert()
+        .success()
+        .stdout("{\"key\":\"TEST_VALUE\",\"value\":\"local\"}\n");
+
+    Ok(())
+}
+
+#[test]
+fn missing_secret() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bwenv")?;
+    cmd.env_remove("BWS_ACCESS_TOKEN")
+        .arg("--profile")
+        .arg("local")
+        .arg("secret")
+        .arg("get")
+        .arg("MISSING_VALUE");
+
+    cmd.assert().code(16).stdout("");
+
+    Ok(())
+}
+
+#[test]
+fn reads_token_from_file() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bwenv")?;
+    cmd.env_remove("BWS_ACCESS_TOKEN")
+        .arg("--token-file")
+        .arg("tests/fixtures/token.txt")
+        .arg("--profile")
+        .arg("local")
+        .arg("secret")
+        .arg("get")
+        .arg("TEST_VALUE");
+
+    cmd.assert().success().stdout("local\n");
+
+    Ok(())
+}
+
+#[test]
+fn passes_remaining_stdin_to_command() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bwenv")?;
+    cmd.env_remove("BWS_ACCESS_TOKEN")
+        .arg("--token-stdin")
+        .arg("--profile")
+        .arg("local")
+        .arg("--")
+        .arg("cat")
+        .write_stdin("stdin-token\nremaining input\n");
+
+    cmd.assert().success().stdout(
+        predicate::str::contains("remaining input")
+            .and(predicate::str::contains("stdin-token").not()),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn passes_token_only_on_request() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bwenv")?;
+    cmd.env("BWS_ACCESS_TOKEN", "unused")
+        .arg("--profile")
+        .arg("local")
+        .arg("--")
+        .arg("printenv")
+        .arg("BWS_ACCESS_TOKEN");
+
+    cmd.assert().failure();
+
+    let mut cmd = Command::cargo_bin("bwenv")?;
+    cmd.env("BWS_ACCESS_TOKEN", "unused")
+        .arg("--profile")
+        .arg("local")
+        .arg("--pass-token")
+        .arg("--")
+        .arg("printenv")
+        .arg("BWS_ACCESS_TOKEN");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("unused"));
+
+    Ok(())
+}
+
+#[test]
+fn shows_token_source() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("bwenv")?;
+    cmd.env_remove("BWS_ACCESS_TOKEN")
+        .arg("--token-file")
+        .arg("tests/fixtures/token.txt")
+        .arg("--profile")
+        .arg("local")
+        .arg("whoami");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("source       command line"));
+
+    Ok(())
+}